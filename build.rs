@@ -34,10 +34,23 @@ fn package_info() -> Result<(PathBuf, Library)> {
     Err(anyhow!("{HEADER_NAME} not found"))
 }
 
+/// Checks whether `version` (e.g. `1.5.1`) is at least `(major, minor)`.
+fn version_at_least(version: &str, (major, minor): (u32, u32)) -> bool {
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+    let version = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+    version >= (major, minor)
+}
+
 fn main() -> Result<()> {
     let (header_path, library) = package_info()?;
     println!("cargo:rerun-if-changed={:?}", header_path.as_path());
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-env=LIBTRACECMD_VERSION={}", library.version);
+    // `tracecmd_iterate_events_reverse` was added in libtracecmd 1.4.
+    println!("cargo:rustc-check-cfg=cfg(libtracecmd_iterate_reverse)");
+    if version_at_least(&library.version, (1, 4)) {
+        println!("cargo:rustc-cfg=libtracecmd_iterate_reverse");
+    }
 
     let bindings = bindgen::Builder::default()
         .header(header_path.to_str().unwrap())
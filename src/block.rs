@@ -0,0 +1,104 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of block I/O events.
+
+use crate::HandleRef;
+use crate::Record;
+use crate::Result;
+
+/// The operation of a block request, decoded from the `rwbs` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockOp {
+    /// Read (`R`).
+    Read,
+    /// Write (`W`).
+    Write,
+    /// Cache flush without data (`F`).
+    Flush,
+    /// Discard (`D`).
+    Discard,
+    /// Secure erase (`DE`).
+    SecureErase,
+    /// Any other operation (`N`).
+    Other,
+}
+
+impl BlockOp {
+    /// Parses the `rwbs` field, which starts with `F` if a cache flush precedes the request,
+    /// followed by a letter for the operation and letters for flags (e.g. `FWS`).
+    fn parse(rwbs: &str) -> Self {
+        let mut op = rwbs;
+        // A leading `F` followed by an operation is a preflush.
+        if op.len() > 1 && op.starts_with('F') {
+            op = &op[1..];
+        }
+        match op.as_bytes().first() {
+            Some(b'R') => BlockOp::Read,
+            Some(b'W') => BlockOp::Write,
+            Some(b'F') => BlockOp::Flush,
+            Some(b'D') if op.as_bytes().get(1) == Some(&b'E') => BlockOp::SecureErase,
+            Some(b'D') => BlockOp::Discard,
+            _ => BlockOp::Other,
+        }
+    }
+}
+
+/// Fields of a block request event such as `block_rq_issue` and `block_rq_complete`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockRq {
+    /// Device number. See [BlockRq::major] and [BlockRq::minor].
+    pub dev: u32,
+    /// First sector of the request.
+    pub sector: u64,
+    /// Number of sectors of the request.
+    pub nr_sector: u32,
+    /// The raw `rwbs` field describing the operation and its flags (e.g. `WS`).
+    pub rwbs: String,
+    /// The operation parsed from `rwbs`.
+    pub op: BlockOp,
+    /// Command name of the task that issued the request. `None` for events without it (e.g.
+    /// `block_rq_complete`).
+    pub comm: Option<String>,
+}
+
+impl BlockRq {
+    /// Gets the major number of the device.
+    pub fn major(&self) -> u32 {
+        self.dev >> 20
+    }
+
+    /// Gets the minor number of the device.
+    pub fn minor(&self) -> u32 {
+        self.dev & ((1 << 20) - 1)
+    }
+}
+
+impl Record<'_> {
+    /// Decodes a `block_rq_*` record (e.g. `block_rq_issue`, `block_rq_complete`).
+    ///
+    /// Returns [crate::Error::FindField] if the record is not a block request event.
+    pub fn block_rq(&self, handle: &HandleRef) -> Result<BlockRq> {
+        let event = self.event(handle)?;
+        let rwbs = event.read_field_str(self, "rwbs")?;
+        Ok(BlockRq {
+            dev: event.read_field_u64(self, "dev")? as u32,
+            sector: event.read_field_u64(self, "sector")?,
+            nr_sector: event.read_field_u64(self, "nr_sector")? as u32,
+            op: BlockOp::parse(&rwbs),
+            rwbs,
+            comm: event.read_field_str(self, "comm").ok(),
+        })
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lazy reading of the saved cmdlines.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Take;
+
+use crate::Input;
+
+/// An iterator over the pids and command names in the saved cmdlines of a file, created by
+/// [Input::cmdlines].
+///
+/// Entries are read from the file one by one, so the whole table is never held in memory. The
+/// iteration ends early if reading the file fails.
+pub struct Cmdlines {
+    reader: BufReader<Take<File>>,
+    line: Vec<u8>,
+}
+
+impl Iterator for Cmdlines {
+    type Item = (i32, String);
+
+    fn next(&mut self) -> Option<(i32, String)> {
+        loop {
+            self.line.clear();
+            if self.reader.read_until(b'\n', &mut self.line).ok()? == 0 {
+                return None;
+            }
+            // Each line is `<pid> <comm>`. Command names are not necessarily valid UTF-8.
+            let line = String::from_utf8_lossy(&self.line);
+            let line = line.trim_end_matches(['\n', '\0']);
+            if let Some((pid, comm)) = line.split_once(' ') {
+                if let Ok(pid) = pid.parse() {
+                    return Some((pid, comm.to_string()));
+                }
+            }
+        }
+    }
+}
+
+impl Input {
+    /// Returns an iterator over the pids and command names in the saved cmdlines of the file,
+    /// in the order they are stored.
+    ///
+    /// Unlike [crate::HandleRef::comm], which looks up the table parsed by libtraceevent, this
+    /// reads the table from the file lazily. Returns `None` if the table can't be read, e.g.
+    /// when it's compressed.
+    pub fn cmdlines(&self) -> Option<Cmdlines> {
+        let (offset, size) = self.file_header()?.cmdlines?;
+        let mut file = File::open(&self.path).ok()?;
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        Some(Cmdlines {
+            reader: BufReader::new(file.take(size)),
+            line: Vec::new(),
+        })
+    }
+
+    /// Counts the entries of the saved cmdlines without keeping them. Returns 0 if the table
+    /// can't be read. See [Input::cmdlines].
+    pub fn num_cmdlines(&self) -> usize {
+        self.cmdlines().map_or(0, |cmdlines| cmdlines.count())
+    }
+
+    /// Collects the saved cmdlines into a map from pids to command names. See
+    /// [Input::cmdlines].
+    pub fn cmdlines_map(&self) -> HashMap<i32, String> {
+        self.cmdlines().into_iter().flatten().collect()
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Saving and restoring the positions of the per-CPU cursors of an input, and random access
+//! to records.
+
+use std::marker::PhantomData;
+
+use crate::bindings;
+use crate::Input;
+use crate::Record;
+
+/// Positions of the per-CPU cursors of an [Input], saved by [Input::save_cursor].
+///
+/// With the `serde` feature, it can be serialized so that a long-running analysis can be
+/// resumed in another process.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CursorState {
+    /// File offset of the last record read from each CPU, indexed by CPU. `None` if no record
+    /// has been read from the CPU.
+    pub offsets: Vec<Option<u64>>,
+}
+
+impl Input {
+    /// Saves the positions of the per-CPU cursors, i.e. which records have been read by
+    /// callbacks and iterators so far.
+    ///
+    /// When called in a callback, the record given to the callback is regarded as read.
+    pub fn save_cursor(&self) -> CursorState {
+        CursorState {
+            offsets: self.last_offsets.borrow().clone(),
+        }
+    }
+
+    /// Moves the per-CPU cursors to the positions saved in `state`, so that the next iteration
+    /// resumes with the records after the ones read before saving.
+    ///
+    /// This is based on `tracecmd_read_at`. Cursors of CPUs without records read in `state`
+    /// are left as is, so `state` should be restored into a newly opened [Input] of the same
+    /// file.
+    pub fn restore_cursor(&mut self, state: CursorState) {
+        for offset in state.offsets.iter().flatten() {
+            let mut cpu = 0;
+            // Reading the record moves the cursor of its CPU to the record after it.
+            let rec = unsafe { bindings::tracecmd_read_at(self.handle, *offset, &mut cpu) };
+            if !rec.is_null() {
+                unsafe { bindings::tracecmd_free_record(rec) };
+            }
+        }
+        *self.last_offsets.borrow_mut() = state.offsets;
+    }
+
+    /// Reads the record at the file offset `offset`, which is a value returned by
+    /// [Record::offset] for a record of the same file, e.g. to jump back to a record bookmarked
+    /// in an earlier pass.
+    ///
+    /// This is a wrapper of `tracecmd_read_at`, which also moves the cursor of the record's CPU
+    /// to the record after it. Returns `None` if there is no record at `offset`. The record
+    /// borrows `self`, so other methods taking `&self` (e.g. [Input::find_event]) can be used
+    /// while it's kept.
+    pub fn read_at(&self, offset: u64) -> Option<Record<'_>> {
+        let mut cpu = 0;
+        let rec = unsafe { bindings::tracecmd_read_at(self.handle, offset, &mut cpu) };
+        if rec.is_null() {
+            return None;
+        }
+        unsafe { self.prepare_record(rec) };
+        Some(Record(rec, PhantomData))
+    }
+}
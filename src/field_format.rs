@@ -0,0 +1,138 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rendering of field values with configurable escaping.
+
+use crate::bindings;
+use crate::cptr_to_string_lossy;
+use crate::Event;
+use crate::Record;
+use crate::Result;
+
+/// How [Event::format_fields] escapes strings and renders arrays.
+///
+/// In both formats, printable ASCII characters other than `"` and `\` are kept as they are, so
+/// the rendered values never contain control characters or invalid UTF-8.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FieldFormat {
+    /// Strings use the short C escapes (`\n`, `\t`, `\r`, `\"` and `\\`) where possible
+    /// and `\xNN` for other bytes. Arrays are rendered in decimal, e.g. `{1, 2, 255}`.
+    #[default]
+    CEscape,
+    /// Strings use `\xNN` for every byte that isn't printable ASCII, as well as for `"` and
+    /// `\`. Arrays are rendered in hex, e.g. `{0x01, 0x02, 0xff}`.
+    Hex,
+}
+
+impl FieldFormat {
+    /// Escapes the bytes of a string, which ends at the first NUL if any.
+    fn escape(self, bytes: &[u8]) -> String {
+        let end = bytes.iter().position(|&c| c == 0).unwrap_or(bytes.len());
+        let mut s = String::new();
+        for &c in &bytes[..end] {
+            match (self, c) {
+                (FieldFormat::CEscape, b'\n') => s.push_str("\\n"),
+                (FieldFormat::CEscape, b'\t') => s.push_str("\\t"),
+                (FieldFormat::CEscape, b'\r') => s.push_str("\\r"),
+                (FieldFormat::CEscape, b'"') => s.push_str("\\\""),
+                (FieldFormat::CEscape, b'\\') => s.push_str("\\\\"),
+                (_, b' '..=b'~') if c != b'"' && c != b'\\' => s.push(c as char),
+                _ => s.push_str(&format!("\\x{c:02x}")),
+            }
+        }
+        s
+    }
+
+    /// Renders an array of integers of `elem_size` bytes each.
+    fn array(self, bytes: &[u8], elem_size: usize, signed: bool) -> String {
+        let elems: Vec<_> = bytes
+            .chunks_exact(elem_size)
+            .map(|elem| match self {
+                FieldFormat::CEscape => number(elem, signed).unwrap_or_default(),
+                FieldFormat::Hex => {
+                    format!("{:#0width$x}", unsigned(elem), width = 2 + 2 * elem_size)
+                }
+            })
+            .collect();
+        format!("{{{}}}", elems.join(", "))
+    }
+}
+
+impl Event {
+    /// Renders the values of the event specific fields (i.e. not the common fields) of `rec`
+    /// in the order of the format, escaping strings and rendering arrays as specified by
+    /// `format`.
+    ///
+    /// Unlike [Event::print_fields], which leaves the escaping to libtraceevent, this never
+    /// emits control characters, so the values can be embedded in other formats such as JSON
+    /// and CSV as they are. Strings are `char` arrays and `__data_loc char[]` fields. Pointers
+    /// are rendered in hex and other numbers in decimal.
+    pub fn format_fields(
+        &self,
+        rec: &Record,
+        format: FieldFormat,
+    ) -> Result<Vec<(String, String)>> {
+        let mut fields = vec![];
+        let mut field = unsafe { (*self.ptr).format.fields };
+        while !field.is_null() {
+            let f = unsafe { &*field };
+            field = f.next;
+            let name = unsafe { cptr_to_string_lossy(f.name) };
+            let type_ = unsafe { cptr_to_string_lossy(f.type_) };
+            let raw = self.field_raw(rec, &name)?;
+            let is = |flag| f.flags & flag as u64 != 0;
+            let signed = is(bindings::tep_format_flags_TEP_FIELD_IS_SIGNED);
+
+            let value = if is(bindings::tep_format_flags_TEP_FIELD_IS_STRING)
+                || (is(bindings::tep_format_flags_TEP_FIELD_IS_ARRAY)
+                    && type_.trim_start_matches("__data_loc ").starts_with("char"))
+            {
+                format!("\"{}\"", format.escape(raw))
+            } else if is(bindings::tep_format_flags_TEP_FIELD_IS_ARRAY) {
+                format.array(raw, (f.elementsize as usize).max(1), signed)
+            } else if is(bindings::tep_format_flags_TEP_FIELD_IS_POINTER) {
+                format!("{:#x}", unsigned(raw))
+            } else {
+                number(raw, signed).unwrap_or_else(|| format.array(raw, 1, false))
+            };
+            fields.push((name, value));
+        }
+        Ok(fields)
+    }
+}
+
+/// Reads an unsigned integer in the byte order of the host from `bytes` of any size up to 8.
+fn unsigned(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    let len = bytes.len().min(8);
+    if cfg!(target_endian = "little") {
+        buf[..len].copy_from_slice(&bytes[..len]);
+    } else {
+        buf[8 - len..].copy_from_slice(&bytes[bytes.len() - len..]);
+    }
+    u64::from_ne_bytes(buf)
+}
+
+/// Renders an integer of 1, 2, 4 or 8 bytes in decimal, or returns `None` for other sizes.
+fn number(bytes: &[u8], signed: bool) -> Option<String> {
+    let val = unsigned(bytes);
+    Some(match (bytes.len(), signed) {
+        (1 | 2 | 4 | 8, false) => val.to_string(),
+        (1, true) => (val as i8).to_string(),
+        (2, true) => (val as i16).to_string(),
+        (4, true) => (val as i32).to_string(),
+        (8, true) => (val as i64).to_string(),
+        _ => return None,
+    })
+}
@@ -0,0 +1,69 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for `trace.dat` files compressed with gzip as a whole (e.g. `trace.dat.gz`).
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use flate2::read::GzDecoder;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A temporary file that is removed when dropped.
+pub(crate) struct TempFile(PathBuf);
+
+impl TempFile {
+    pub(crate) fn path(&self) -> &str {
+        self.0.to_str().expect("temporary path must be UTF-8")
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Checks whether the file at `path` starts with the gzip magic number.
+pub(crate) fn is_gzip(path: &str) -> std::io::Result<bool> {
+    let mut magic = [0; GZIP_MAGIC.len()];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(magic == GZIP_MAGIC),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Decompresses the gzip file at `path` into a new temporary file.
+pub(crate) fn decompress(path: &str) -> std::io::Result<TempFile> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let temp = TempFile(std::env::temp_dir().join(format!(
+        "libtracecmd-{}-{}.dat",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )));
+    let mut out = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp.0)?;
+    std::io::copy(&mut GzDecoder::new(File::open(path)?), &mut out)?;
+
+    Ok(temp)
+}
@@ -0,0 +1,682 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A minimal reader of the `trace.dat` file header.
+//!
+//! libtracecmd parses the whole header when a file is opened, but most of it is not available
+//! through its public API. This module reads the parts this crate needs directly from the file.
+//! See [trace-cmd.dat.v6(5)](https://man7.org/linux/man-pages/man5/trace-cmd.dat.v6.5.html) and
+//! [trace-cmd.dat.v7(5)](https://man7.org/linux/man-pages/man5/trace-cmd.dat.v7.5.html) for the
+//! file formats.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Cursor;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::Error;
+use crate::Result;
+
+const MAGIC: [u8; 10] = [0x17, 0x08, 0x44, b't', b'r', b'a', b'c', b'i', b'n', b'g'];
+
+/// ID of the option terminating a list of options.
+const OPTION_DONE: u16 = 0;
+/// ID of the option with the content of `per_cpu/cpu*/stats` at the end of recording.
+const OPTION_CPUSTAT: u16 = 2;
+/// ID of the option describing a trace buffer (v7).
+const OPTION_BUFFER: u16 = 3;
+/// ID of the option with the content of the `trace_clock` file.
+const OPTION_TRACECLOCK: u16 = 4;
+/// ID of the option with the `uname` of the traced machine.
+const OPTION_UNAME: u16 = 5;
+/// ID of the option pointing to the section with `header_page` and `header_event` (v7).
+const OPTION_HEADER_INFO: u16 = 16;
+/// ID of the option pointing to the section with the saved cmdlines (v7).
+const OPTION_CMDLINES: u16 = 21;
+
+/// Flag of a v7 section whose content is compressed.
+const SECTION_COMPRESSED: u16 = 1;
+
+/// Location of the ring buffer data of a CPU in the file.
+pub(crate) struct CpuData {
+    /// Size of the data in bytes.
+    pub(crate) size: u64,
+}
+
+/// The parts of a `trace.dat` header read by this crate.
+pub(crate) struct FileHeader {
+    /// Version of the file format.
+    pub(crate) version: u32,
+    /// Trace clock used for recording (e.g. `local`), if it's recorded.
+    pub(crate) clock: Option<String>,
+    /// Format of the `header_page` of the ring buffer. `None` if it's compressed.
+    pub(crate) header_page: Option<String>,
+    /// Format of the `header_event` of the ring buffer. `None` if it's compressed.
+    pub(crate) header_event: Option<String>,
+    /// Ring buffer data of the top-level trace buffer per CPU.
+    pub(crate) cpu_data: Vec<CpuData>,
+    /// `uname -a`-like description of the traced machine.
+    pub(crate) uname: Option<String>,
+    /// All options in the file with their ids, in the order they appear.
+    pub(crate) options: Vec<(u16, Vec<u8>)>,
+    /// Name of the algorithm compressing sections of a v7 file (e.g. `zstd`). `None` if the
+    /// file is not compressed.
+    pub(crate) compression: Option<String>,
+    /// Offset and size of the content of `saved_cmdlines` in the file. `None` if it's
+    /// compressed.
+    pub(crate) cmdlines: Option<(u64, u64)>,
+}
+
+impl FileHeader {
+    /// Reads the header of the `trace.dat` file at `path`.
+    pub(crate) fn read(path: &str) -> Result<Self> {
+        let file = File::open(path).map_err(Error::Io)?;
+        let mut reader = Reader {
+            len: file.metadata().map_err(Error::Io)?.len(),
+            inner: BufReader::new(file),
+            big_endian: false,
+        };
+
+        let mut magic = [0; MAGIC.len()];
+        reader.inner.read_exact(&mut magic).map_err(header_error)?;
+        if magic != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = reader.read_cstr().map_err(header_error)?;
+        let version = match version.parse::<u32>() {
+            Ok(version @ (6 | 7)) => version,
+            _ => return Err(Error::UnsupportedVersion(version)),
+        };
+        reader.read_rest(version).map_err(header_error)
+    }
+
+    /// Gets the number of entries that the ring buffer of `cpu` held at the end of recording,
+    /// from the `entries:` line of the CPU's stats.
+    pub(crate) fn cpu_entries(&self, cpu: i32) -> Option<u64> {
+        self.cpu_stat(cpu, "entries")
+    }
+
+    /// Gets the number of events that the ring buffer of `cpu` overwrote, from the `overrun:` line
+    /// of the CPU's stats.
+    pub(crate) fn cpu_overrun(&self, cpu: i32) -> Option<u64> {
+        self.cpu_stat(cpu, "overrun")
+    }
+
+    /// Gets the value of the line `<name>: <value>` in the stats of `cpu`.
+    ///
+    /// trace-cmd writes a CPUSTAT option per CPU, and the stats of each CPU follow a
+    /// `CPU: <cpu>` line, so all of the options are searched.
+    fn cpu_stat(&self, cpu: i32, name: &str) -> Option<u64> {
+        let options = self.options.iter().filter(|(id, _)| *id == OPTION_CPUSTAT);
+        for (_, data) in options {
+            let stats = option_string(data);
+            let mut current = None;
+            for line in stats.lines() {
+                if let Some(n) = line.strip_prefix("CPU:") {
+                    current = n.trim().parse::<i32>().ok();
+                } else if let Some(value) = line
+                    .strip_prefix(name)
+                    .and_then(|rest| rest.strip_prefix(':'))
+                {
+                    if current == Some(cpu) {
+                        return value.trim().parse().ok();
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Gets the selected clock from the content of the `trace_clock` file, which lists available
+/// clocks with the selected one in brackets (e.g. `[local] global counter`).
+fn selected_clock(trace_clock: &str) -> Option<String> {
+    let clock = match (trace_clock.find('['), trace_clock.find(']')) {
+        (Some(start), Some(end)) if start < end => &trace_clock[start + 1..end],
+        _ => trace_clock.trim(),
+    };
+    (!clock.is_empty()).then(|| clock.to_string())
+}
+
+/// Converts an error while reading a header into the error describing the failure.
+fn header_error(e: std::io::Error) -> Error {
+    match e.kind() {
+        ErrorKind::UnexpectedEof => Error::TruncatedHeader,
+        ErrorKind::InvalidData => Error::InvalidHeader(e.to_string()),
+        _ => Error::Io(e),
+    }
+}
+
+/// Converts the content of an option holding a NUL-terminated string.
+fn option_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// A reader of integers and strings in the byte order of the file.
+struct Reader<R> {
+    inner: R,
+    /// Length of the whole content of `inner`, which bounds the sizes read from the file.
+    len: u64,
+    big_endian: bool,
+}
+
+impl<R: Read + Seek> Reader<R> {
+    fn read_u8(&mut self) -> std::io::Result<u8> {
+        let mut buf = [0; 1];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u16(&mut self) -> std::io::Result<u16> {
+        let mut buf = [0; 2];
+        self.inner.read_exact(&mut buf)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(buf)
+        } else {
+            u16::from_le_bytes(buf)
+        })
+    }
+
+    fn read_u32(&mut self) -> std::io::Result<u32> {
+        let mut buf = [0; 4];
+        self.inner.read_exact(&mut buf)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(buf)
+        } else {
+            u32::from_le_bytes(buf)
+        })
+    }
+
+    fn read_u64(&mut self) -> std::io::Result<u64> {
+        let mut buf = [0; 8];
+        self.inner.read_exact(&mut buf)?;
+        Ok(self.u64_from_bytes(buf))
+    }
+
+    fn u64_from_bytes(&self, buf: [u8; 8]) -> u64 {
+        if self.big_endian {
+            u64::from_be_bytes(buf)
+        } else {
+            u64::from_le_bytes(buf)
+        }
+    }
+
+    /// Reads a `u64` at the beginning of `data`.
+    fn u64_from(&self, data: &[u8]) -> std::io::Result<u64> {
+        let buf = data
+            .get(..8)
+            .ok_or_else(|| invalid_data("option too short"))?;
+        Ok(self.u64_from_bytes(buf.try_into().unwrap()))
+    }
+
+    fn read_bytes(&mut self, len: u64) -> std::io::Result<Vec<u8>> {
+        // Sizes are read from the file, so they are checked before allocating.
+        if len > self.len.saturating_sub(self.inner.stream_position()?) {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let mut buf = vec![];
+        (&mut self.inner).take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        Ok(buf)
+    }
+
+    /// Reads a NUL-terminated string.
+    fn read_cstr(&mut self) -> std::io::Result<String> {
+        let mut buf = vec![];
+        loop {
+            match self.read_u8()? {
+                0 => break,
+                c => buf.push(c),
+            }
+        }
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    /// Reads a NUL-terminated string and checks that it's `expected`.
+    fn expect_cstr(&mut self, expected: &str) -> std::io::Result<()> {
+        if self.read_cstr()? != expected {
+            return Err(invalid_data(&format!("{expected} not found")));
+        }
+        Ok(())
+    }
+
+    /// Skips `len` bytes.
+    fn skip(&mut self, len: u64) -> std::io::Result<()> {
+        let len = i64::try_from(len).map_err(|_| invalid_data("size too large"))?;
+        self.inner.seek(SeekFrom::Current(len))?;
+        Ok(())
+    }
+
+    /// Reads the rest of the header after the version string.
+    fn read_rest(&mut self, version: u32) -> std::io::Result<FileHeader> {
+        self.big_endian = self.read_u8()? != 0;
+        let _long_size = self.read_u8()?;
+        let _page_size = self.read_u32()?;
+
+        if version < 7 {
+            self.read_v6()
+        } else {
+            self.read_v7()
+        }
+    }
+
+    /// Reads the rest of a v6 header after the page size.
+    fn read_v6(&mut self) -> std::io::Result<FileHeader> {
+        let (header_page, header_event) = self.read_header_info()?;
+
+        // Formats of ftrace's own events.
+        for _ in 0..self.read_u32()? {
+            let size = self.read_u64()?;
+            self.skip(size)?;
+        }
+        // Formats of the other events, grouped by systems.
+        for _ in 0..self.read_u32()? {
+            let _system = self.read_cstr()?;
+            for _ in 0..self.read_u32()? {
+                let size = self.read_u64()?;
+                self.skip(size)?;
+            }
+        }
+        // kallsyms and printk formats.
+        for _ in 0..2 {
+            let size = self.read_u32()?;
+            self.skip(size as u64)?;
+        }
+        // Saved cmdlines.
+        let size = self.read_u64()?;
+        let cmdlines = Some((self.inner.stream_position()?, size));
+        self.skip(size)?;
+
+        let cpus = self.read_u32()?;
+        let mut data_type = self.read_cstr()?;
+        let mut options = vec![];
+        if data_type == "options  " {
+            options = self.read_options_v6()?;
+            data_type = self.read_cstr()?;
+        }
+        let mut cpu_data = vec![];
+        if data_type == "flyrecord" {
+            for _ in 0..cpus {
+                let _offset = self.read_u64()?;
+                let size = self.read_u64()?;
+                cpu_data.push(CpuData { size });
+            }
+        }
+
+        Ok(FileHeader {
+            version: 6,
+            clock: options
+                .iter()
+                .find(|(id, _)| *id == OPTION_TRACECLOCK)
+                .and_then(|(_, data)| selected_clock(&option_string(data))),
+            header_page,
+            header_event,
+            cpu_data,
+            uname: options
+                .iter()
+                .find(|(id, _)| *id == OPTION_UNAME)
+                .map(|(_, data)| option_string(data)),
+            options,
+            compression: None,
+            cmdlines,
+        })
+    }
+
+    /// Reads the rest of a v7 header after the page size.
+    fn read_v7(&mut self) -> std::io::Result<FileHeader> {
+        let compression = self.read_cstr()?;
+        let _compression_version = self.read_cstr()?;
+        let options_offset = self.read_u64()?;
+        let options = self.read_options_v7(options_offset)?;
+
+        let mut header = FileHeader {
+            version: 7,
+            clock: None,
+            header_page: None,
+            header_event: None,
+            cpu_data: vec![],
+            uname: None,
+            options: vec![],
+            compression: (!compression.is_empty() && compression != "none").then_some(compression),
+            cmdlines: None,
+        };
+        for (id, data) in &options {
+            match *id {
+                OPTION_HEADER_INFO => {
+                    let offset = self.u64_from(data)?;
+                    if self.enter_section(offset)?.is_some() {
+                        (header.header_page, header.header_event) = self.read_header_info()?;
+                    }
+                }
+                OPTION_CMDLINES => {
+                    let offset = self.u64_from(data)?;
+                    if self.enter_section(offset)?.is_some() {
+                        let size = self.read_u64()?;
+                        header.cmdlines = Some((self.inner.stream_position()?, size));
+                    }
+                }
+                OPTION_UNAME => header.uname = Some(option_string(data)),
+                OPTION_TRACECLOCK => header.clock = selected_clock(&option_string(data)),
+                OPTION_BUFFER => {
+                    let mut option = self.slice_reader(data);
+                    let _offset = option.read_u64()?;
+                    let name = option.read_cstr()?;
+                    if !name.is_empty() {
+                        // A buffer of an instance.
+                        continue;
+                    }
+                    let clock = option.read_cstr()?;
+                    if header.clock.is_none() {
+                        header.clock = selected_clock(&clock);
+                    }
+                    let _page_size = option.read_u32()?;
+                    for _ in 0..option.read_u32()? {
+                        let _cpu = option.read_u32()?;
+                        let _offset = option.read_u64()?;
+                        let size = option.read_u64()?;
+                        header.cpu_data.push(CpuData { size });
+                    }
+                }
+                _ => {}
+            }
+        }
+        header.options = options;
+        Ok(header)
+    }
+
+    /// Creates a reader of the content of an option.
+    fn slice_reader<'a>(&self, data: &'a [u8]) -> Reader<Cursor<&'a [u8]>> {
+        Reader {
+            inner: Cursor::new(data),
+            len: data.len() as u64,
+            big_endian: self.big_endian,
+        }
+    }
+
+    /// Reads the options of a v6 file until the terminating option.
+    fn read_options_v6(&mut self) -> std::io::Result<Vec<(u16, Vec<u8>)>> {
+        let mut options = vec![];
+        loop {
+            let id = self.read_u16()?;
+            if id == OPTION_DONE {
+                break;
+            }
+            let size = self.read_u32()?;
+            options.push((id, self.read_bytes(size as u64)?));
+        }
+        Ok(options)
+    }
+
+    /// Reads the `header_page` and `header_event` formats.
+    fn read_header_info(&mut self) -> std::io::Result<(Option<String>, Option<String>)> {
+        self.expect_cstr("header_page")?;
+        let size = self.read_u64()?;
+        let header_page = String::from_utf8_lossy(&self.read_bytes(size)?).into_owned();
+
+        self.expect_cstr("header_event")?;
+        let size = self.read_u64()?;
+        let header_event = String::from_utf8_lossy(&self.read_bytes(size)?).into_owned();
+
+        Ok((Some(header_page), Some(header_event)))
+    }
+
+    /// Seeks to the v7 section at `offset` and reads its header. Returns the size of the section,
+    /// or `None` if the section is compressed.
+    fn enter_section(&mut self, offset: u64) -> std::io::Result<Option<u64>> {
+        self.inner.seek(SeekFrom::Start(offset))?;
+        let _id = self.read_u16()?;
+        let flags = self.read_u16()?;
+        let _string_id = self.read_u32()?;
+        let size = self.read_u64()?;
+        if flags & SECTION_COMPRESSED != 0 {
+            return Ok(None);
+        }
+        Ok(Some(size))
+    }
+
+    /// Reads all options by following the chain of v7 option sections from `offset`.
+    fn read_options_v7(&mut self, mut offset: u64) -> std::io::Result<Vec<(u16, Vec<u8>)>> {
+        let mut options = vec![];
+        let mut visited = HashSet::new();
+        while offset != 0 {
+            if !visited.insert(offset) {
+                return Err(invalid_data("loop in option sections"));
+            }
+            if self.enter_section(offset)?.is_none() {
+                break;
+            }
+            loop {
+                let id = self.read_u16()?;
+                let size = self.read_u32()?;
+                let data = self.read_bytes(size as u64)?;
+                if id == OPTION_DONE {
+                    offset = self.u64_from(&data)?;
+                    break;
+                }
+                options.push((id, data));
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER_PAGE: &str = "field: u64 timestamp;";
+    const HEADER_EVENT: &str = "type_len : 5 bits";
+
+    /// Builds a little-endian v6 file with a single CPU and `options`.
+    fn v6(options: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(b"6\0");
+        buf.extend_from_slice(&[0, 8]);
+        buf.extend_from_slice(&4096u32.to_le_bytes());
+        buf.extend_from_slice(&header_info());
+        // No ftrace events, systems, kallsyms and printk formats.
+        for _ in 0..4 {
+            buf.extend_from_slice(&0u32.to_le_bytes());
+        }
+        let cmdlines = b"1 init\n";
+        buf.extend_from_slice(&(cmdlines.len() as u64).to_le_bytes());
+        buf.extend_from_slice(cmdlines);
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        if !options.is_empty() {
+            buf.extend_from_slice(b"options  \0");
+            for (id, data) in options {
+                buf.extend_from_slice(&option(*id, data));
+            }
+            buf.extend_from_slice(&OPTION_DONE.to_le_bytes());
+        }
+        buf.extend_from_slice(b"flyrecord\0");
+        buf.extend_from_slice(&8192u64.to_le_bytes());
+        buf.extend_from_slice(&4096u64.to_le_bytes());
+        buf
+    }
+
+    /// Builds the `header_page` and `header_event` sections.
+    fn header_info() -> Vec<u8> {
+        let mut buf = vec![];
+        for (name, format) in [("header_page", HEADER_PAGE), ("header_event", HEADER_EVENT)] {
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(0);
+            buf.extend_from_slice(&(format.len() as u64).to_le_bytes());
+            buf.extend_from_slice(format.as_bytes());
+        }
+        buf
+    }
+
+    fn option(id: u16, data: &[u8]) -> Vec<u8> {
+        let mut buf = id.to_le_bytes().to_vec();
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    /// Appends a v7 section with `content` to `buf` and returns its offset.
+    fn section(buf: &mut Vec<u8>, content: &[u8]) -> u64 {
+        let offset = buf.len() as u64;
+        buf.extend_from_slice(&[0; 8]);
+        buf.extend_from_slice(&(content.len() as u64).to_le_bytes());
+        buf.extend_from_slice(content);
+        offset
+    }
+
+    /// Builds the beginning of a little-endian v7 file, whose options start at `options_offset`.
+    fn v7_start(options_offset: u64) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(b"7\0");
+        buf.extend_from_slice(&[0, 8]);
+        buf.extend_from_slice(&4096u32.to_le_bytes());
+        buf.extend_from_slice(b"none\0\0");
+        buf.extend_from_slice(&options_offset.to_le_bytes());
+        buf
+    }
+
+    /// Writes `content` to a temporary file named after `name` and reads its header.
+    fn read(name: &str, content: &[u8]) -> Result<FileHeader> {
+        let path = std::env::temp_dir().join(format!(
+            "libtracecmd-header-{name}-{}.dat",
+            std::process::id()
+        ));
+        std::fs::write(&path, content).unwrap();
+        let header = FileHeader::read(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        header
+    }
+
+    #[test]
+    fn v6_without_options() {
+        let header = read("v6", &v6(&[])).unwrap();
+        assert_eq!(header.version, 6);
+        assert_eq!(header.header_page.as_deref(), Some(HEADER_PAGE));
+        assert_eq!(header.header_event.as_deref(), Some(HEADER_EVENT));
+        assert_eq!(header.cpu_data.len(), 1);
+        assert_eq!(header.cpu_data[0].size, 4096);
+        assert!(header.options.is_empty());
+        assert_eq!(header.clock, None);
+        assert_eq!(header.uname, None);
+        assert_eq!(header.compression, None);
+        let (offset, size) = header.cmdlines.unwrap();
+        assert_eq!(size, 7);
+        let file = v6(&[]);
+        assert_eq!(&file[offset as usize..][..7], b"1 init\n");
+    }
+
+    #[test]
+    fn v6_with_options() {
+        // trace-cmd writes the stats of each CPU in a separate option.
+        let stats0 = b"CPU: 0\nentries: 10\noverrun: 0\ncommit overrun: 0\nbytes: 5120\n\0";
+        let stats1 = b"CPU: 1\nentries: 20\noverrun: 3\ncommit overrun: 7\nbytes: 9216\n\0";
+        let header = read(
+            "v6-options",
+            &v6(&[
+                (OPTION_TRACECLOCK, b"[local] global counter\0"),
+                (OPTION_UNAME, b"Linux host 6.1.0 x86_64\0"),
+                (OPTION_CPUSTAT, stats0),
+                (OPTION_CPUSTAT, stats1),
+                (100, b"custom"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(header.clock.as_deref(), Some("local"));
+        assert_eq!(header.uname.as_deref(), Some("Linux host 6.1.0 x86_64"));
+        assert_eq!(header.cpu_entries(0), Some(10));
+        assert_eq!(header.cpu_entries(1), Some(20));
+        assert_eq!(header.cpu_entries(2), None);
+        // `commit overrun:` is a different counter.
+        assert_eq!(header.cpu_overrun(0), Some(0));
+        assert_eq!(header.cpu_overrun(1), Some(3));
+        assert_eq!(header.cpu_overrun(2), None);
+        assert_eq!(header.options.len(), 5);
+        assert_eq!(header.options[4], (100, b"custom".to_vec()));
+        assert_eq!(header.cpu_data.len(), 1);
+    }
+
+    #[test]
+    fn v7_options() {
+        // The options are at the end, after the header info section.
+        let mut buf = v7_start(0);
+        let header_info_offset = section(&mut buf, &header_info());
+        let mut options = option(OPTION_HEADER_INFO, &header_info_offset.to_le_bytes());
+        options.extend_from_slice(&option(OPTION_UNAME, b"Linux host\0"));
+        options.extend_from_slice(&option(OPTION_DONE, &0u64.to_le_bytes()));
+        let options_offset = section(&mut buf, &options);
+        buf[MAGIC.len() + 14..][..8].copy_from_slice(&options_offset.to_le_bytes());
+
+        let header = read("v7", &buf).unwrap();
+        assert_eq!(header.version, 7);
+        assert_eq!(header.header_page.as_deref(), Some(HEADER_PAGE));
+        assert_eq!(header.header_event.as_deref(), Some(HEADER_EVENT));
+        assert_eq!(header.uname.as_deref(), Some("Linux host"));
+        assert_eq!(header.compression, None);
+        assert_eq!(header.options.len(), 2);
+    }
+
+    #[test]
+    fn v7_loop_in_option_sections() {
+        let mut buf = v7_start(0);
+        let offset = buf.len() as u64;
+        buf[MAGIC.len() + 14..][..8].copy_from_slice(&offset.to_le_bytes());
+        // The last option points back to the same section.
+        section(&mut buf, &option(OPTION_DONE, &offset.to_le_bytes()));
+        assert!(matches!(
+            read("v7-loop", &buf),
+            Err(Error::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn option_larger_than_file() {
+        let mut buf = v6(&[(OPTION_UNAME, b"Linux\0")]);
+        // Make the size of the option claim 4 GiB.
+        let pos = buf.windows(6).position(|w| w == b"Linux\0").unwrap();
+        buf[pos - 4..pos].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            read("huge-option", &buf),
+            Err(Error::TruncatedHeader)
+        ));
+    }
+
+    #[test]
+    fn bad_files() {
+        assert!(matches!(
+            read("bad-magic", b"\x17\x08\x44tracinX6\0"),
+            Err(Error::BadMagic)
+        ));
+        let mut buf = v6(&[]);
+        buf[MAGIC.len()] = b'5';
+        assert!(matches!(
+            read("bad-version", &buf),
+            Err(Error::UnsupportedVersion(version)) if version == "5"
+        ));
+        let buf = v6(&[]);
+        assert!(matches!(
+            read("truncated", &buf[..buf.len() - 4]),
+            Err(Error::TruncatedHeader)
+        ));
+    }
+}
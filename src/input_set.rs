@@ -0,0 +1,239 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sets of inputs whose records are merged.
+
+use std::cmp::Ordering;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::marker::PhantomData;
+
+use crate::bindings;
+use crate::Error;
+use crate::Input;
+use crate::Record;
+use crate::Result;
+
+/// A set of inputs recorded at the same time, e.g. on a host and its guests with
+/// `trace-cmd agent`.
+pub struct InputSet {
+    inputs: Vec<Input>,
+}
+
+impl InputSet {
+    /// Creates a set of `inputs`. The index of each input in `inputs` is used to tell which
+    /// input a record comes from.
+    pub fn new(inputs: Vec<Input>) -> Self {
+        InputSet { inputs }
+    }
+
+    /// Opens the files in the directory `path` whose names match `pattern` and creates a set of
+    /// them, e.g. `cpu*.dat` for `cpu0.dat`, `cpu1.dat` and so on.
+    ///
+    /// `pattern` matches whole file names, where `*` matches any sequence of characters and `?`
+    /// matches any single character. Files are sorted by names with numbers compared by their
+    /// values (e.g. `cpu2.dat` comes before `cpu10.dat`), which gives their indices in the set.
+    /// Fails with [Error::OpenFile] naming the first file that can't be opened.
+    pub fn from_dir(path: &str, pattern: &str) -> Result<Self> {
+        let mut names = vec![];
+        for entry in std::fs::read_dir(path).map_err(Error::Io)? {
+            let entry = entry.map_err(Error::Io)?;
+            if !entry.file_type().map_err(Error::Io)?.is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if wildcard_match(pattern.as_bytes(), name.as_bytes()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort_by(|a, b| natural_cmp(a, b).then_with(|| a.cmp(b)));
+
+        let mut inputs = vec![];
+        for name in names {
+            let file = std::path::Path::new(path).join(&name);
+            let file = file.to_string_lossy();
+            let input = Input::new(&file).map_err(|_| Error::OpenFile(file.to_string()))?;
+            inputs.push(input);
+        }
+        Ok(InputSet::new(inputs))
+    }
+
+    /// Gets the inputs in the set.
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    /// Gets the inputs in the set mutably, e.g. to pass them to [crate::Handler::process_multi].
+    pub fn inputs_mut(&mut self) -> &mut [Input] {
+        &mut self.inputs
+    }
+
+    /// Takes the inputs out of the set.
+    pub fn into_inputs(self) -> Vec<Input> {
+        self.inputs
+    }
+
+    /// Returns an iterator over the records of all CPUs of all inputs in timestamp order. Each
+    /// record is paired with the index of the input that it comes from.
+    ///
+    /// Records are read from the current position of each CPU's cursor, in the same way as
+    /// [Input::cpu_records]. Like [crate::Handler::process_multi], records are ordered by the
+    /// timestamps adjusted by libtracecmd.
+    pub fn merged_records(&mut self) -> MergedIter<'_> {
+        let mut heads = BinaryHeap::new();
+        for (index, input) in self.inputs.iter().enumerate() {
+            for cpu in 0..input.header_cpus() as i32 {
+                if let Some(ts) = peek_ts(input, cpu) {
+                    heads.push(Reverse((ts, index, cpu)));
+                }
+            }
+        }
+        MergedIter {
+            inputs: &mut self.inputs,
+            heads,
+        }
+    }
+}
+
+/// Checks whether `name` matches `pattern`, in which `*` matches any sequence and `?` matches
+/// any single byte.
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            wildcard_match(&pattern[1..], name)
+                || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Compares file names by comparing runs of digits by their values and other characters as
+/// they are.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let len_a = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let len_b = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let (na, nb) = (
+                a[..len_a].trim_start_matches('0'),
+                b[..len_b].trim_start_matches('0'),
+            );
+            let ord = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            (a, b) = (&a[len_a..], &b[len_b..]);
+        } else {
+            if ca != cb {
+                return ca.cmp(&cb);
+            }
+            (a, b) = (&a[ca.len_utf8()..], &b[cb.len_utf8()..]);
+        }
+    }
+}
+
+/// Gets the timestamp of the next record of `cpu` in `input` without consuming it.
+fn peek_ts(input: &Input, cpu: i32) -> Option<u64> {
+    let rec = unsafe { bindings::tracecmd_peek_data(input.handle, cpu) };
+    if rec.is_null() {
+        return None;
+    }
+    Some(unsafe { (*rec).ts })
+}
+
+/// An iterator over records of multiple inputs in timestamp order, created by
+/// [InputSet::merged_records].
+pub struct MergedIter<'a> {
+    inputs: &'a mut [Input],
+    /// Timestamps of the next records of CPUs that have records left: (timestamp, index of the
+    /// input, cpu).
+    heads: BinaryHeap<Reverse<(u64, usize, i32)>>,
+}
+
+impl<'a> Iterator for MergedIter<'a> {
+    type Item = (usize, Record<'a>);
+
+    fn next(&mut self) -> Option<(usize, Record<'a>)> {
+        let Reverse((_, index, cpu)) = self.heads.pop()?;
+        let input = &self.inputs[index];
+        let rec = unsafe { bindings::tracecmd_read_data(input.handle, cpu) };
+        if let Some(ts) = peek_ts(input, cpu) {
+            self.heads.push(Reverse((ts, index, cpu)));
+        }
+        if rec.is_null() {
+            return self.next();
+        }
+        unsafe { input.prepare_record(rec) };
+        Some((index, Record(rec, PhantomData)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard() {
+        for (pattern, name, expected) in [
+            ("*.dat", "cpu0.dat", true),
+            ("*.dat", ".dat", true),
+            ("*.dat", "cpu0.dat.gz", false),
+            ("cpu?.dat", "cpu1.dat", true),
+            ("cpu?.dat", "cpu10.dat", false),
+            ("cpu*.dat", "cpu10.dat", true),
+            ("*", "", true),
+            ("?", "", false),
+            ("*a*b", "xaxxb", true),
+            ("*a*b", "xbxa", false),
+            ("trace.dat", "trace.dat", true),
+            ("trace.dat", "trace.da", false),
+        ] {
+            assert_eq!(
+                wildcard_match(pattern.as_bytes(), name.as_bytes()),
+                expected,
+                "{pattern} {name}"
+            );
+        }
+    }
+
+    #[test]
+    fn natural_order() {
+        for (a, b, expected) in [
+            ("cpu2.dat", "cpu10.dat", Ordering::Less),
+            ("cpu10.dat", "cpu9.dat", Ordering::Greater),
+            ("cpu1.dat", "cpu1.dat", Ordering::Equal),
+            ("cpu01.dat", "cpu1.dat", Ordering::Equal),
+            ("cpu1.dat", "cpu1a.dat", Ordering::Less),
+            ("a10b2", "a10b10", Ordering::Less),
+            ("host-a", "host-b", Ordering::Less),
+            ("cpu", "cpu0", Ordering::Less),
+            (
+                "99999999999999999999999",
+                "100000000000000000000000",
+                Ordering::Less,
+            ),
+        ] {
+            assert_eq!(natural_cmp(a, b), expected, "{a} {b}");
+            assert_eq!(natural_cmp(b, a), expected.reverse(), "{b} {a}");
+        }
+    }
+}
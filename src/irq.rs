@@ -0,0 +1,110 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of hard and soft interrupt events.
+
+use crate::Error;
+use crate::HandleRef;
+use crate::Record;
+use crate::Result;
+
+/// Names of softirq vectors, indexed by the `vec` field, as shown by `/proc/softirqs`.
+const SOFTIRQ_NAMES: [&str; 10] = [
+    "HI", "TIMER", "NET_TX", "NET_RX", "BLOCK", "IRQ_POLL", "TASKLET", "SCHED", "HRTIMER", "RCU",
+];
+
+/// A decoded `irq` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IrqEvent {
+    /// `irq_handler_entry`: a handler of a hard interrupt starts.
+    HandlerEntry {
+        /// Interrupt number.
+        irq: i32,
+        /// Name of the handler's device (e.g. `eth0`).
+        name: String,
+    },
+    /// `irq_handler_exit`: a handler of a hard interrupt returns.
+    HandlerExit {
+        /// Interrupt number.
+        irq: i32,
+        /// Return value of the handler, e.g. 1 (`IRQ_HANDLED`) or 0 (`IRQ_NONE`).
+        ret: i32,
+    },
+    /// `softirq_raise`: a softirq is raised.
+    SoftirqRaise {
+        /// Softirq vector. See [IrqEvent::softirq_name].
+        vec: u32,
+    },
+    /// `softirq_entry`: a softirq handler starts.
+    SoftirqEntry {
+        /// Softirq vector. See [IrqEvent::softirq_name].
+        vec: u32,
+    },
+    /// `softirq_exit`: a softirq handler returns.
+    SoftirqExit {
+        /// Softirq vector. See [IrqEvent::softirq_name].
+        vec: u32,
+    },
+}
+
+impl IrqEvent {
+    /// Gets the interrupt number of a hard interrupt event.
+    pub fn irq(&self) -> Option<i32> {
+        match self {
+            IrqEvent::HandlerEntry { irq, .. } | IrqEvent::HandlerExit { irq, .. } => Some(*irq),
+            _ => None,
+        }
+    }
+
+    /// Gets the name of the vector of a softirq event (e.g. `NET_RX`).
+    pub fn softirq_name(&self) -> Option<&'static str> {
+        match self {
+            IrqEvent::SoftirqRaise { vec }
+            | IrqEvent::SoftirqEntry { vec }
+            | IrqEvent::SoftirqExit { vec } => SOFTIRQ_NAMES.get(*vec as usize).copied(),
+            _ => None,
+        }
+    }
+}
+
+impl Record<'_> {
+    /// Decodes an `irq_handler_entry`, `irq_handler_exit`, `softirq_raise`, `softirq_entry` or
+    /// `softirq_exit` record.
+    ///
+    /// Returns [Error::FindField] if the record is not one of these events.
+    pub fn irq_event(&self, handle: &HandleRef) -> Result<IrqEvent> {
+        let event = self.event(handle)?;
+        let number = |name| event.read_field_u64(self, name);
+        Ok(match event.name_str() {
+            "irq_handler_entry" => IrqEvent::HandlerEntry {
+                irq: number("irq")? as i32,
+                name: event.read_field_str(self, "name")?,
+            },
+            "irq_handler_exit" => IrqEvent::HandlerExit {
+                irq: number("irq")? as i32,
+                ret: number("ret")? as i32,
+            },
+            "softirq_raise" => IrqEvent::SoftirqRaise {
+                vec: number("vec")? as u32,
+            },
+            "softirq_entry" => IrqEvent::SoftirqEntry {
+                vec: number("vec")? as u32,
+            },
+            "softirq_exit" => IrqEvent::SoftirqExit {
+                vec: number("vec")? as u32,
+            },
+            _ => return Err(Error::FindField),
+        })
+    }
+}
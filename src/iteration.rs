@@ -0,0 +1,170 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A builder to configure how [Handler::callback] is called.
+
+use std::marker::PhantomData;
+
+use crate::bindings;
+use crate::handler_callback;
+use crate::iterate_input_cpus;
+use crate::supports_iterate_reverse;
+use crate::Error;
+use crate::Filter;
+use crate::HandleRef;
+use crate::Handler;
+use crate::Input;
+use crate::Result;
+use crate::STOP;
+
+/// Order in which records are passed to a handler. See [Handler::process_ordered].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IterationOrder {
+    /// All records of CPU 0 in timestamp order, then all records of CPU 1, and so on.
+    PerCpu,
+    /// Records of all CPUs merged in timestamp order. This is the order of [Handler::process].
+    #[default]
+    TimeSorted,
+}
+
+/// Configures an iteration over an [Input] and runs a [Handler] with it.
+///
+/// Options that are not specified default to the behavior of [Handler::process].
+///
+/// # Example
+///
+/// ```no_run
+/// # use libtracecmd::{Handler, Input, Record};
+/// # struct MyHandler;
+/// # impl Handler for MyHandler {
+/// #   type AccumulatedData = ();
+/// #   fn callback(_: &mut Input, _: &mut Record, _: i32, _: &mut ()) -> i32 { 0 }
+/// # }
+/// use libtracecmd::Iteration;
+///
+/// let mut input = Input::new("trace.dat").unwrap();
+/// let data = Iteration::new(&mut input)
+///     .cpus(&[0, 1])
+///     .filter("sched_switch")
+///     .range(1_000_000, 2_000_000)
+///     .run::<MyHandler>()
+///     .unwrap();
+/// ```
+pub struct Iteration<'a> {
+    input: &'a mut Input,
+    cpus: Option<Vec<i32>>,
+    filter: Option<String>,
+    range: Option<(u64, u64)>,
+    reverse: bool,
+}
+
+impl<'a> Iteration<'a> {
+    /// Creates an iteration over all records of `input`.
+    pub fn new(input: &'a mut Input) -> Self {
+        Iteration {
+            input,
+            cpus: None,
+            filter: None,
+            range: None,
+            reverse: false,
+        }
+    }
+
+    /// Only iterates over records of `cpus`.
+    pub fn cpus(mut self, cpus: &[i32]) -> Self {
+        self.cpus = Some(cpus.to_vec());
+        self
+    }
+
+    /// Only passes records matching the filter expression `expr` to the handler. See
+    /// [Filter::compile] for the syntax.
+    pub fn filter(mut self, expr: &str) -> Self {
+        self.filter = Some(expr.to_string());
+        self
+    }
+
+    /// Only passes records whose timestamps are in `[start_ts, end_ts]` to the handler.
+    ///
+    /// The iteration stops at the first record out of the range in the direction of the
+    /// iteration, as [Handler::process_range] does.
+    pub fn range(mut self, start_ts: u64, end_ts: u64) -> Self {
+        self.range = Some((start_ts, end_ts));
+        self
+    }
+
+    /// Iterates from the newest record to the oldest one if `reverse` is true.
+    ///
+    /// This is a wrapper of `tracecmd_iterate_events_reverse`, which requires libtracecmd 1.4 or
+    /// later (see [crate::supports_iterate_reverse]).
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Runs the iteration by calling [Handler::callback] of `H` for each record passing the
+    /// options and returns the accumulated data.
+    ///
+    /// Fails with [Error::InvalidFilter] if the filter doesn't compile, with [Error::Unsupported]
+    /// if [Self::reverse] is set but libtracecmd doesn't support it, or with [Error::Iterate]
+    /// if the callback returns a value other than [crate::CONTINUE] and [crate::STOP].
+    pub fn run<H: Handler>(self) -> Result<H::AccumulatedData> {
+        if self.reverse && !supports_iterate_reverse() {
+            return Err(Error::Unsupported(
+                "reverse iteration requires libtracecmd 1.4".to_string(),
+            ));
+        }
+        let tep = unsafe { bindings::tracecmd_get_tep(self.input.handle) };
+        if tep.is_null() {
+            return Err(Error::Handle);
+        }
+        // `tep` is owned by `self.input`, which outlives the filter.
+        let handle = HandleRef(tep, PhantomData);
+        let filter = self
+            .filter
+            .as_deref()
+            .map(|expr| Filter::compile(&handle, expr))
+            .transpose()?;
+        let reverse = self.reverse;
+        let range = self.range;
+
+        let mut data: H::AccumulatedData = Default::default();
+        let ret = iterate_input_cpus(
+            self.input,
+            self.cpus.as_deref(),
+            reverse,
+            handler_callback::<H, _>(&mut data, |_index, input, rec, cpu, data| {
+                if let Some((start_ts, end_ts)) = range {
+                    let ts = rec.ts();
+                    let (before, after) = if reverse {
+                        (ts > end_ts, ts < start_ts)
+                    } else {
+                        (ts < start_ts, ts > end_ts)
+                    };
+                    if before {
+                        return 0;
+                    }
+                    if after {
+                        return STOP;
+                    }
+                }
+                if filter.as_ref().is_some_and(|filter| !filter.matches(rec)) {
+                    return 0;
+                }
+                H::callback(input, rec, cpu, data)
+            }),
+        );
+        ret.map_err(Error::Iterate)?;
+        Ok(data)
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Analyses of KVM events.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::try_iterate_events;
+use crate::Input;
+use crate::Result;
+
+impl Input {
+    /// Computes how long each vCPU ran the guest, keyed by the pid of the vCPU thread and the
+    /// vCPU id.
+    ///
+    /// Each `kvm_entry` record is paired with the next `kvm_exit` record of the same thread, and
+    /// the time between them is added to the vCPU given by the `vcpu_id` field of `kvm_entry`.
+    /// An entry without a following exit (e.g. at the end of the trace) is not counted. The
+    /// trace needs to be recorded with `trace-cmd record -e kvm:kvm_entry -e kvm:kvm_exit`.
+    pub fn vcpu_runtimes(&mut self) -> Result<HashMap<(i32, i32), Duration>> {
+        let handle = self.handle_ref()?;
+        // Pending `kvm_entry` of each thread: (vcpu id, timestamp).
+        let mut entries: HashMap<i32, (i32, u64)> = HashMap::new();
+        let mut runtimes: HashMap<(i32, i32), Duration> = HashMap::new();
+        try_iterate_events(self, |rec, _cpu| {
+            let event = self.find_event(rec)?;
+            let pid = handle.pid(rec);
+            match event.name_str() {
+                "kvm_entry" => {
+                    let vcpu = event.read_field_u64(rec, "vcpu_id")?;
+                    entries.insert(pid, (vcpu as i32, rec.ts()));
+                }
+                "kvm_exit" => {
+                    if let Some((vcpu, start)) = entries.remove(&pid) {
+                        *runtimes.entry((pid, vcpu)).or_default() +=
+                            Duration::from_nanos(rec.ts().saturating_sub(start));
+                    }
+                }
+                _ => {}
+            }
+            Ok(true)
+        })?;
+
+        Ok(runtimes)
+    }
+}
@@ -87,13 +87,77 @@
     non_upper_case_globals,
     non_camel_case_types,
     non_snake_case,
-    dead_code
+    dead_code,
+    missing_docs
 )]
 mod bindings {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
+/// Raw bindings of libtracecmd and libtraceevent generated by bindgen, for calling functions
+/// that this crate doesn't wrap yet. Available with the `raw` feature.
+#[cfg(feature = "raw")]
+pub mod raw {
+    pub use crate::bindings::*;
+}
+mod block;
+mod cmdlines;
+mod cursor;
+mod field_format;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod header;
+mod input_set;
+mod irq;
+mod iteration;
+mod kvm;
+mod merge;
+mod meta;
+mod mm;
+mod output;
+mod pairing;
+mod print_fmt;
+mod probe;
+mod sched;
+mod summary;
+mod syscall;
+mod text;
+mod tracer;
+mod version;
+
+use std::cell::OnceCell;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::marker::PhantomData;
 
+pub use block::BlockOp;
+pub use block::BlockRq;
+pub use cmdlines::Cmdlines;
+pub use cursor::CursorState;
+pub use field_format::FieldFormat;
+use header::FileHeader;
+pub use input_set::InputSet;
+pub use input_set::MergedIter;
+pub use irq::IrqEvent;
+pub use iteration::Iteration;
+pub use iteration::IterationOrder;
+pub use merge::Mergeable;
+pub use meta::RecordMeta;
+pub use mm::PageEvent;
+pub use output::Output;
+pub use pairing::Pairing;
+pub use pairing::Pairings;
+pub use probe::ProbeResult;
+pub use sched::SchedMigrate;
+pub use sched::SchedWakeup;
+pub use summary::Report;
+pub use summary::ReportOptions;
+pub use summary::TraceSummary;
+pub use text::TextOptions;
 use thiserror::Error;
+pub use version::libtracecmd_version;
+pub use version::supports_compression;
+pub use version::supports_iterate_reverse;
 
 /// Errors that can happen while processing tracing data.
 #[derive(Error, Debug)]
@@ -101,6 +165,9 @@ pub enum Error {
     /// Failed to open .dat file
     #[error("failed to open .dat file")]
     Open,
+    /// Failed to open the .dat file at the path
+    #[error("failed to open .dat file: {0}")]
+    OpenFile(String),
     /// Failed to get `tep_handle`
     #[error("failed to get tep_handle")]
     Handle,
@@ -122,6 +189,39 @@ pub enum Error {
     /// Failed to read a field
     #[error("failed to read a field")]
     ReadField,
+    /// Failed to iterate over events
+    #[error("failed to iterate over events: {0}")]
+    Iterate(i32),
+    /// I/O error
+    #[error("I/O error: {0}")]
+    Io(std::io::Error),
+    /// Failed to parse kallsyms
+    #[error("failed to parse kallsyms")]
+    ParseKallsyms,
+    /// Failed to decompress a file
+    #[error("failed to decompress a file: {0}")]
+    Decompress(std::io::Error),
+    /// Invalid filter
+    #[error("invalid filter: {0}")]
+    InvalidFilter(String),
+    /// The file doesn't start with the magic number of `trace.dat`
+    #[error("bad magic number")]
+    BadMagic,
+    /// The version of the file format is not supported
+    #[error("unsupported file version: {0}")]
+    UnsupportedVersion(String),
+    /// The file ends in the middle of the header
+    #[error("truncated header")]
+    TruncatedHeader,
+    /// The header is malformed
+    #[error("invalid header: {0}")]
+    InvalidHeader(String),
+    /// The operation needs a newer version of libtracecmd than the crate was built against
+    #[error("unsupported by libtracecmd {}: {0}", env!("LIBTRACECMD_VERSION"))]
+    Unsupported(String),
+    /// Invalid definition or record of a synthetic event for [Output]
+    #[error("invalid synthetic event: {0}")]
+    InvalidSyntheticEvent(String),
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -131,98 +231,1645 @@ unsafe fn cptr_to_string(ptr: *mut i8) -> Result<String> {
     Ok(c_str.to_str().map_err(Error::InvalidString)?.to_string())
 }
 
+/// Similar to [cptr_to_string], but replaces invalid UTF-8 sequences with `U+FFFD`.
+unsafe fn cptr_to_string_lossy(ptr: *const std::ffi::c_char) -> String {
+    unsafe { std::ffi::CStr::from_ptr(ptr) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Formats a timestamp in nanoseconds as `secs.usecs` (e.g. `1234.567890`) in the same way as
+/// `trace-cmd report` does.
+///
+/// The timestamp is rounded to the nearest microsecond. Unlike `trace-cmd report`, the seconds
+/// are not padded with spaces.
+pub fn format_ts(ts: u64) -> String {
+    let usecs = ts / 1000 + u64::from(ts % 1000 >= 500);
+    format!("{}.{:06}", usecs / 1_000_000, usecs % 1_000_000)
+}
+
 /// A wrapper of `tracecmd_input` represnting a `trace.dat` file given as the input.
-pub struct Input(*mut bindings::tracecmd_input);
+pub struct Input {
+    handle: *mut bindings::tracecmd_input,
+    path: String,
+    /// Cache of events looked up by [Input::find_event], keyed by type id.
+    events: RefCell<HashMap<i32, Event>>,
+    /// The file header, read on demand by [Input::file_header].
+    header: OnceCell<Option<FileHeader>>,
+    /// Offset set by [Input::set_ts_offset].
+    ts_offset: i64,
+    /// Multiplier and shift set by [Input::set_ts_scale].
+    ts_scale: Option<(u64, i32)>,
+    /// Offset of the last record read from each CPU, for [Input::save_cursor].
+    last_offsets: RefCell<Vec<Option<u64>>>,
+    /// Thread group ids of tasks, built on demand by [Input::tgid_for_pid].
+    tgids: OnceCell<HashMap<i32, i32>>,
+    /// Whether [Input::prepare_record] learns command names for [Input::comm_from_pid].
+    track_comms: bool,
+    /// Command names learned from scheduler events, keyed by pids.
+    observed_comms: RefCell<HashMap<i32, String>>,
+    /// Names of system calls by number, built on demand by [Input::syscall_name].
+    syscall_names: OnceCell<HashMap<i64, String>>,
+    /// The decompressed file that `path` points to, if the input was compressed.
+    #[cfg(feature = "gzip")]
+    temp: Option<std::sync::Arc<gzip::TempFile>>,
+}
 
 impl Input {
     /// Opens a given `trace.dat` file and create `Input`.
     pub fn new(path: &str) -> Result<Self> {
+        let c_path = std::ffi::CString::new(path).map_err(|_| Error::Open)?;
         // TODO: Support open flags.
-        let handle = unsafe { bindings::tracecmd_open(path.as_ptr() as *mut i8, 0) };
+        let handle = unsafe { bindings::tracecmd_open(c_path.as_ptr(), 0) };
         if handle.is_null() {
             return Err(Error::Open);
         }
 
-        Ok(Input(handle))
+        Ok(Input {
+            handle,
+            path: path.to_string(),
+            events: Default::default(),
+            header: OnceCell::new(),
+            ts_offset: 0,
+            ts_scale: None,
+            last_offsets: Default::default(),
+            tgids: OnceCell::new(),
+            track_comms: false,
+            observed_comms: Default::default(),
+            syscall_names: OnceCell::new(),
+            #[cfg(feature = "gzip")]
+            temp: None,
+        })
+    }
+
+    /// Opens a `trace.dat` file that may be compressed with gzip as a whole (e.g.
+    /// `trace.dat.gz`) and create `Input`.
+    ///
+    /// A gzip-compressed file is decompressed into a temporary file, which is removed once the
+    /// `Input` and all `Input`s reopened from it are dropped. Other files are opened as
+    /// [Input::new] does.
+    #[cfg(feature = "gzip")]
+    pub fn open_maybe_compressed(path: &str) -> Result<Self> {
+        if !gzip::is_gzip(path).map_err(Error::Io)? {
+            return Self::new(path);
+        }
+
+        let temp = gzip::decompress(path).map_err(Error::Decompress)?;
+        let mut input = Self::new(temp.path())?;
+        input.temp = Some(std::sync::Arc::new(temp));
+        Ok(input)
+    }
+
+    /// Opens the file that `self` was created from again and returns a new `Input`.
+    ///
+    /// The returned `Input` has its own cursor, so it can be iterated independently of `self`
+    /// (e.g. in another thread). Timestamp adjustments set with [Input::set_ts_offset] and
+    /// [Input::set_ts_scale] are carried over. Note that the file's headers are parsed again.
+    pub fn reopen(&self) -> Result<Self> {
+        let mut input = Self::new(&self.path)?;
+        #[cfg(feature = "gzip")]
+        {
+            input.temp = self.temp.clone();
+        }
+        input.set_ts_offset(self.ts_offset);
+        input.ts_scale = self.ts_scale;
+        Ok(input)
+    }
+
+    /// Sets the offset in nanoseconds added to the timestamps of all records, replacing the
+    /// offset set before.
+    ///
+    /// This is a wrapper of `tracecmd_add_ts_offset`, so the offset is applied by libtracecmd
+    /// together with its own corrections and also affects the order in which
+    /// [Handler::process_multi] merges records of multiple inputs. It should be called before
+    /// iterating over records, as records already read from the file are not adjusted.
+    pub fn set_ts_offset(&mut self, offset: i64) {
+        unsafe { bindings::tracecmd_add_ts_offset(self.handle, offset - self.ts_offset) };
+        self.ts_offset = offset;
+    }
+
+    /// Scales the timestamps of records delivered by this input to `(ts * mult) >> shift`, in
+    /// the same way as the TSC-to-nanosecond conversion. A negative `shift` shifts to the left.
+    ///
+    /// Unlike [Input::set_ts_offset], the scale is applied by this crate when a record is passed
+    /// to a callback or returned by [Input::cpu_records], after all adjustments by libtracecmd
+    /// (including the offset). So, it doesn't affect the order in which
+    /// [Handler::process_multi] merges records of multiple inputs.
+    pub fn set_ts_scale(&mut self, mult: u64, shift: i32) {
+        self.ts_scale = Some((mult, shift));
+    }
+
+    /// Prepares a record read from this input before it's passed to users: applies the scale
+    /// set with [Input::set_ts_scale], remembers the position for [Input::save_cursor] and
+    /// learns command names if [Input::set_track_comms] is enabled.
+    ///
+    /// # Safety
+    ///
+    /// `rec` must be a valid pointer.
+    unsafe fn prepare_record(&self, rec: *mut bindings::tep_record) {
+        let cpu = (*rec).cpu as usize;
+        let mut offsets = self.last_offsets.borrow_mut();
+        if cpu >= offsets.len() {
+            offsets.resize(cpu + 1, None);
+        }
+        offsets[cpu] = Some((*rec).offset);
+
+        if let Some((mult, shift)) = self.ts_scale {
+            let ts = (*rec).ts as u128 * mult as u128;
+            let ts = if shift >= 0 {
+                ts >> shift
+            } else {
+                ts << -shift
+            };
+            (*rec).ts = ts as u64;
+        }
+
+        if self.track_comms {
+            let rec = std::mem::ManuallyDrop::new(Record(rec, PhantomData));
+            self.observe_comms(&rec);
+        }
+    }
+
+    /// Reads the file header on the first call, or returns the cached one. Returns `None` if the
+    /// header can't be read.
+    fn file_header(&self) -> Option<&FileHeader> {
+        self.header
+            .get_or_init(|| FileHeader::read(&self.path).ok())
+            .as_ref()
+    }
+
+    /// Checks that the file is a well-formed `trace.dat`.
+    ///
+    /// This reads the magic number, the version and the header sections of the file (including
+    /// the sizes of the event format sections and the per-CPU data sections in v6 files), and
+    /// checks that the parser found at least one event format. Each failure is reported with a
+    /// specific error such as [Error::BadMagic] or [Error::TruncatedHeader].
+    pub fn validate(&mut self) -> Result<()> {
+        FileHeader::read(&self.path)?;
+        let handle = self.handle_ref()?;
+        if unsafe { bindings::tep_get_events_count(handle.0) } <= 0 {
+            return Err(Error::InvalidHeader("no event formats".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over the records of `cpu` in timestamp order.
+    ///
+    /// This reads records with `tracecmd_read_data` from the current position of the CPU's
+    /// cursor, which is the beginning of its data after the input is opened. Unlike
+    /// [Handler::process], records of different CPUs are not merged, so it can be used to merge
+    /// per-CPU streams in a custom way.
+    pub fn cpu_records(&mut self, cpu: i32) -> CpuRecordIter<'_> {
+        CpuRecordIter { input: self, cpu }
+    }
+
+    /// Returns an iterator over the records of all CPUs in timestamp order, each paired with its
+    /// event.
+    ///
+    /// This is a read-only alternative to [Handler] for the common case:
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), libtracecmd::Error> {
+    /// let mut input = libtracecmd::Input::new("trace.dat")?;
+    /// for decoded in input.decoded_records() {
+    ///     let (event, rec) = decoded?;
+    ///     match event.name.as_str() {
+    ///         "sched_switch" => println!("{}: switch", rec.ts_string()),
+    ///         _ => {}
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Records are read with `tracecmd_read_next_data` from the current positions of the CPUs'
+    /// cursors, in the same way as [Input::cpu_records]. Events are looked up with
+    /// [Input::find_event], so they are cached. If the event of a record can't be found, an error
+    /// is yielded for the record and the iteration goes on.
+    pub fn decoded_records(&mut self) -> DecodedRecordIter<'_> {
+        DecodedRecordIter { input: self }
+    }
+
+    /// Finds the record whose timestamp is the closest to `ts` among all CPUs.
+    ///
+    /// For each CPU, libtracecmd binary-searches the ring buffer pages for the one that should
+    /// contain `ts` (`tracecmd_set_cpu_to_timestamp`), and then records in the page are read
+    /// one by one until one at or after `ts` is found. So, the result is the exact nearest
+    /// record, at the cost of reading up to a page per CPU. Ties are broken in favor of the
+    /// lower CPU.
+    ///
+    /// This moves the cursor of each CPU past the first record at or after `ts` (or to the end
+    /// of the CPU's data if there is no such record), so the next record read from a CPU is
+    /// the one after it, not the found record. Use [Input::save_cursor] and
+    /// [Input::restore_cursor] to go back. Returns `None` if the input has no records.
+    pub fn find_record_near(&self, ts: u64) -> Option<Record<'_>> {
+        let mut nearest: Option<Record> = None;
+        for cpu in 0..self.header_cpus() as i32 {
+            if !self.seek_cpu_to(cpu, ts) {
+                continue;
+            }
+            // The last record before `ts` and the first one at or after `ts`.
+            let mut before = None;
+            let mut after = None;
+            loop {
+                let rec = unsafe { bindings::tracecmd_read_data(self.handle, cpu) };
+                if rec.is_null() {
+                    break;
+                }
+                unsafe { self.prepare_record(rec) };
+                let rec = Record(rec, PhantomData);
+                if rec.ts() >= ts {
+                    after = Some(rec);
+                    break;
+                }
+                before = Some(rec);
+            }
+            for candidate in [before, after].into_iter().flatten() {
+                let distance = candidate.ts().abs_diff(ts);
+                let closer = match &nearest {
+                    Some(n) => distance < n.ts().abs_diff(ts),
+                    None => true,
+                };
+                if closer {
+                    nearest = Some(candidate);
+                }
+            }
+        }
+        nearest
+    }
+
+    /// Moves the cursor of `cpu` to the ring buffer page that should contain `ts` with
+    /// `tracecmd_set_cpu_to_timestamp`. Returns `false` if the cursor can't be moved.
+    fn seek_cpu_to(&self, cpu: i32, ts: u64) -> bool {
+        // Pages are searched with the timestamps before the scale of `set_ts_scale` is applied.
+        let seek_ts = match self.ts_scale {
+            Some((mult, shift)) if mult != 0 => {
+                let ts = ts as u128;
+                let ts = if shift >= 0 {
+                    ts << shift
+                } else {
+                    ts >> -shift
+                };
+                (ts / mult as u128).min(u64::MAX as u128) as u64
+            }
+            _ => ts,
+        };
+        unsafe { bindings::tracecmd_set_cpu_to_timestamp(self.handle, cpu, seek_ts) >= 0 }
+    }
+
+    /// Moves the cursors of all CPUs to the pages that should contain `ts`. See
+    /// [Self::seek_cpu_to].
+    fn seek_cpus_to(&self, ts: u64) {
+        for cpu in 0..self.header_cpus() as i32 {
+            self.seek_cpu_to(cpu, ts);
+        }
+    }
+
+    /// Gets the raw bytes of the ring buffer page that holds the next record of `cpu`, i.e. the
+    /// page at the current position of the CPU's cursor. Returns `None` if the CPU has no
+    /// records left.
+    ///
+    /// The page is owned by libtracecmd and is valid only until the cursor moves to the next
+    /// page, which is why it borrows `self` mutably. The layout of the page is described by
+    /// [Input::header_page_format].
+    pub fn cpu_page(&mut self, cpu: i32) -> Option<&[u8]> {
+        let rec = unsafe { bindings::tracecmd_peek_data(self.handle, cpu) };
+        if rec.is_null() {
+            return None;
+        }
+        let page = unsafe { bindings::tracecmd_record_page(self.handle, rec) };
+        if page.is_null() {
+            return None;
+        }
+        let page_size =
+            unsafe { bindings::tep_get_page_size(bindings::tracecmd_get_tep(self.handle)) };
+        Some(unsafe { std::slice::from_raw_parts(page as *const u8, page_size as usize) })
+    }
+
+    /// Gets the content of the first option with the id `id` in the file header.
+    ///
+    /// Options are keyed by numeric ids, not by names: each option in `trace.dat` is stored as
+    /// an id, a size and the content, in every version of the format, so there is no name to
+    /// look up. Besides the options defined by trace-cmd, this returns options that other tools
+    /// add to the file with `tracecmd_add_option()`, which should use ids not defined by
+    /// trace-cmd. A tool that needs named blobs can agree on an id and put the name in the
+    /// content. Returns `None` if there is no such option or the options can't be read (e.g.
+    /// they are compressed).
+    pub fn custom_option(&self, id: u16) -> Option<Vec<u8>> {
+        self.file_header()?
+            .options
+            .iter()
+            .find(|(option_id, _)| *option_id == id)
+            .map(|(_, data)| data.clone())
+    }
+
+    /// Gets the number of entries recorded in the ring buffer of `cpu`, as reported by the
+    /// kernel's `per_cpu/cpu<N>/stats` when the recording finished.
+    ///
+    /// This reads the value stored in the file without iterating records. It may differ from
+    /// the number of records in the file, e.g. when events were overwritten. Returns `None` if
+    /// the file has no stats for `cpu` or its options can't be read.
+    pub fn cpu_entries(&self, cpu: i32) -> Option<u64> {
+        self.file_header()?.cpu_entries(cpu)
+    }
+
+    /// Gets the format of the ring buffer's page header (`header_page`) recorded in the file.
+    ///
+    /// Returns `None` if it can't be read, e.g. when the header section is compressed.
+    pub fn header_page_format(&self) -> Option<String> {
+        self.file_header()?.header_page.clone()
+    }
+
+    /// Gets the format of the ring buffer's event header (`header_event`) recorded in the file.
+    ///
+    /// Returns `None` if it can't be read, e.g. when the header section is compressed.
+    pub fn header_event_format(&self) -> Option<String> {
+        self.file_header()?.header_event.clone()
+    }
+
+    /// Gets the number of CPUs that have ring buffer data in the file.
+    ///
+    /// CPUs that were offline or recorded no data while tracing are not counted, so this can be
+    /// smaller than [Self::header_cpus]. Returns `0` if the file header can't be read.
+    pub fn cpus(&self) -> usize {
+        self.file_header()
+            .map(|header| header.cpu_data.iter().filter(|data| data.size > 0).count())
+            .unwrap_or(0)
+    }
+
+    /// Gets the total size in bytes of the ring buffer data of all CPUs of the top-level buffer,
+    /// as recorded in the locations of the per-CPU data sections. The headers, the options and
+    /// the buffers of instances are not included.
+    ///
+    /// In v7 files with compressed data, this is the size after compression. Returns `None` if
+    /// the file header can't be read or has no per-CPU data (e.g. a file in the latency
+    /// format).
+    pub fn data_size(&self) -> Option<u64> {
+        let header = self.file_header()?;
+        if header.cpu_data.is_empty() {
+            return None;
+        }
+        Some(header.cpu_data.iter().map(|data| data.size).sum())
+    }
+
+    /// Gets the number of CPUs recorded in the file header, which is the number of CPUs of the
+    /// traced machine.
+    ///
+    /// This is the value that `tracecmd_cpus()` returns and that the parser uses as the number of
+    /// CPUs, including CPUs without any data. Records from CPU `0` to `header_cpus() - 1` can
+    /// appear in the trace.
+    pub fn header_cpus(&self) -> usize {
+        unsafe { bindings::tep_get_cpus(bindings::tracecmd_get_tep(self.handle)) }.max(0) as usize
+    }
+
+    /// Gets the resolution in nanoseconds of the timestamps of records, derived from the trace
+    /// clock recorded in the file.
+    ///
+    /// Returns `Some(1)` for clocks counting nanoseconds (`local`, `global`, `perf`, `mono`,
+    /// `mono_raw`, `boot` and `tai`) and for `x86-tsc` when libtracecmd converts TSC cycles to
+    /// nanoseconds. Returns `None` if timestamps are not in nanoseconds (`counter`, `uptime`
+    /// counting jiffies, or `x86-tsc` without conversion) or if the clock is unknown.
+    pub fn clock_resolution_ns(&self) -> Option<u64> {
+        match self.file_header()?.clock.as_deref()? {
+            "local" | "global" | "perf" | "mono" | "mono_raw" | "boot" | "tai" => Some(1),
+            "x86-tsc" => {
+                let (mut mult, mut shift, mut offset) = (0, 0, 0);
+                let ret = unsafe {
+                    bindings::tracecmd_get_tsc2nsec(self.handle, &mut mult, &mut shift, &mut offset)
+                };
+                (ret == 0 && mult != 0).then_some(1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Gets the raw `tracecmd_input` pointer, which is owned by `self`.
+    ///
+    /// The pointer must not be closed, and any state changed through it (e.g. the cursors) is
+    /// visible to `self`.
+    #[cfg(feature = "raw")]
+    pub fn as_raw(&self) -> *mut bindings::tracecmd_input {
+        self.handle
+    }
+
+    /// Gets `Handle` from the `Input`.
+    pub fn handle_ref(&self) -> Result<HandleRef<'_>> {
+        let ret = unsafe { bindings::tracecmd_get_tep(self.handle) };
+        if ret.is_null() {
+            Err(Error::Handle)
+        } else {
+            Ok(HandleRef(ret, PhantomData))
+        }
+    }
+
+    /// Gets an `Event` corresponding to a given `rec`.
+    ///
+    /// Events are cached per type id, so looking up the same event repeatedly is cheap.
+    pub fn find_event(&self, rec: &Record) -> Result<Event> {
+        let handle = self.handle_ref()?;
+        let id = unsafe { bindings::tep_data_type(handle.0, rec.0) };
+        if let Some(event) = self.events.borrow().get(&id) {
+            return Ok(event.clone());
+        }
+
+        let ptr = unsafe { bindings::tep_find_event(handle.0, id) };
+        if ptr.is_null() {
+            return Err(Error::FindEvent);
+        }
+        let event = unsafe { Event::from_ptr(ptr) }.expect("string");
+        self.events.borrow_mut().insert(id, event.clone());
+        Ok(event)
+    }
+
+    /// Counts the events that the ring buffer dropped while recording.
+    ///
+    /// This combines two sources:
+    ///
+    /// - [Record::missed_events] summed over all records of all CPUs. A gap whose size is
+    ///   unknown is counted as one dropped event.
+    /// - The `overrun:` counters of all CPUs in the CPU statistics saved in the file (the
+    ///   content of `per_cpu/cpu*/stats` at the end of recording), i.e. the events that the
+    ///   ring buffer overwrote. CPUs without statistics count as zero.
+    ///
+    /// So, a non-zero value always means that the trace is incomplete.
+    pub fn total_dropped_events(&mut self) -> Result<u64> {
+        let mut total = self.total_overruns();
+        iterate_events(self, |rec, _cpu| {
+            total += dropped_events(rec);
+            0
+        })
+        .map_err(Error::Iterate)?;
+        Ok(total)
+    }
+
+    /// Sums the `overrun:` counters of the CPU statistics in the file header for
+    /// [Input::total_dropped_events].
+    fn total_overruns(&self) -> u64 {
+        let Some(header) = self.file_header() else {
+            return 0;
+        };
+        (0..self.header_cpus() as i32)
+            .filter_map(|cpu| header.cpu_overrun(cpu))
+            .sum()
+    }
+
+    /// Gets the names of the events that were enabled when the trace was recorded, sorted by
+    /// name.
+    ///
+    /// `trace-cmd record` saves the formats of the enabled events only (all events of a system
+    /// if the whole system is enabled), so these are the events whose formats are in the file,
+    /// excluding ftrace's own events such as `function`, whose formats are always saved. An
+    /// event in this list may still have no records. Returns an empty vector if the parser can't
+    /// be obtained.
+    pub fn enabled_events(&self) -> Vec<String> {
+        let Ok(handle) = self.handle_ref() else {
+            return vec![];
+        };
+        let mut names = vec![];
+        // The returned array is owned by the parser and terminated with NULL.
+        let mut events = unsafe {
+            bindings::tep_list_events(handle.0, bindings::tep_event_sort_type_TEP_EVENT_SORT_NAME)
+        };
+        if events.is_null() {
+            return names;
+        }
+        while !unsafe { *events }.is_null() {
+            let event = unsafe { *events };
+            let flags = EventFlags::from_bits_retain(unsafe { (*event).flags } as u32);
+            if !flags.contains(EventFlags::FTRACE) {
+                if let Ok(name) = unsafe { cptr_to_string((*event).name) } {
+                    names.push(name);
+                }
+            }
+            events = unsafe { events.add(1) };
+        }
+        names
+    }
+
+    /// Folds all records into a value, calling `f` with the accumulated value, each record, its
+    /// event and its CPU in timestamp order.
+    ///
+    /// This is a lightweight alternative to [Handler] and [EventHandler] for one-off analyses,
+    /// which doesn't require defining a type. Records of unknown events are skipped.
+    ///
+    /// ```no_run
+    /// # use libtracecmd::Input;
+    /// let mut input = Input::new("trace.dat").unwrap();
+    /// let switches = input
+    ///     .process_fold(0, |n, _rec, event, _cpu| {
+    ///         if event.name_str() == "sched_switch" { n + 1 } else { n }
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn process_fold<B, F>(&mut self, init: B, mut f: F) -> Result<B>
+    where
+        F: FnMut(B, &Record, &Event, i32) -> B,
+    {
+        let mut acc = Some(init);
+        iterate_input(self, |input, rec, cpu| {
+            if let Ok(event) = input.find_event(rec) {
+                let b = acc.take().expect("accumulator must be present");
+                acc = Some(f(b, rec, &event, cpu));
+            }
+            0
+        })
+        .map_err(Error::Iterate)?;
+        Ok(acc.expect("accumulator must be present"))
+    }
+
+    /// Gets the timestamps of all records of the event `event` in timestamp order.
+    ///
+    /// Whether a record is of the event is decided by its type id, so the name of the event is
+    /// compared only once per event type. Returns an empty vector if there is no such event.
+    pub fn event_timestamps(&mut self, event: &str) -> Result<Vec<u64>> {
+        let handle = self.handle_ref()?;
+        let mut matches: HashMap<i32, bool> = HashMap::new();
+        let mut timestamps = vec![];
+        iterate_events(self, |rec, _cpu| {
+            let id = unsafe { bindings::tep_data_type(handle.0, rec.0) };
+            let matched = *matches
+                .entry(id)
+                .or_insert_with(|| self.find_event(rec).is_ok_and(|e| e.name_str() == event));
+            if matched {
+                timestamps.push(rec.ts());
+            }
+            0
+        })
+        .map_err(Error::Iterate)?;
+        Ok(timestamps)
+    }
+
+    /// Counts the values of the numeric field `field` of the event `event` in buckets of width
+    /// `bucket`.
+    ///
+    /// Each key of the returned map is the lower bound of a bucket (i.e. a multiple of
+    /// `bucket`), and the value is the number of records whose field values fall in `[key, key
+    /// + bucket)`. Empty buckets are not included. A `bucket` of 0 is treated as 1, i.e. values
+    /// are counted as they are. Fails with [Error::FindField] if the event doesn't have `field`.
+    pub fn field_histogram(
+        &mut self,
+        event: &str,
+        field: &str,
+        bucket: u64,
+    ) -> Result<BTreeMap<u64, u64>> {
+        let bucket = bucket.max(1);
+        let mut histogram = BTreeMap::new();
+        try_iterate_events(self, |rec, _cpu| {
+            let e = match self.find_event(rec) {
+                Ok(e) if e.name_str() == event => e,
+                _ => return Ok(true),
+            };
+            let val = e.read_field_u64(rec, field)?;
+            *histogram.entry(val / bucket * bucket).or_insert(0) += 1;
+            Ok(true)
+        })?;
+        Ok(histogram)
+    }
+
+    /// Counts the records of each CPU. The returned vector is indexed by CPU and has at least
+    /// [Input::header_cpus] elements.
+    ///
+    /// If reading records fails in the middle, the counts of the records read so far are
+    /// returned.
+    pub fn per_cpu_counts(&mut self) -> Vec<u64> {
+        let mut counts = vec![0; self.header_cpus()];
+        let _ = iterate_events(self, |_rec, cpu| {
+            count_cpu(&mut counts, cpu);
+            0
+        });
+        counts
+    }
+
+    /// Checks whether the input has any record of the function graph tracer
+    /// (`ftrace/funcgraph_entry` or `ftrace/funcgraph_exit`), i.e. whether it was recorded with
+    /// `trace-cmd record -p function_graph`.
+    ///
+    /// The formats of these events are saved in every trace, so this looks for an actual record
+    /// and stops at the first one found. The file is scanned from the beginning with a new
+    /// handle opened by [Input::reopen], so the cursors of `self` are not moved. Returns `false`
+    /// if the records can't be read.
+    pub fn has_function_graph(&self) -> bool {
+        let Ok(input) = self.reopen() else {
+            return false;
+        };
+        let Ok(handle) = input.handle_ref() else {
+            return false;
+        };
+        let ids: Vec<i32> = [c"funcgraph_entry", c"funcgraph_exit"]
+            .iter()
+            .filter_map(|name| {
+                let event = unsafe {
+                    bindings::tep_find_event_by_name(handle.0, c"ftrace".as_ptr(), name.as_ptr())
+                };
+                (!event.is_null()).then(|| unsafe { (*event).id })
+            })
+            .collect();
+        if ids.is_empty() {
+            return false;
+        }
+
+        let mut found = false;
+        let _ = iterate_events(&input, |rec, _cpu| {
+            if ids.contains(&unsafe { bindings::tep_data_type(handle.0, rec.0) }) {
+                found = true;
+                return STOP;
+            }
+            0
+        });
+        found
+    }
+
+    /// Collapses the kernel stack traces recorded in the input into the folded format consumed
+    /// by [flamegraph.pl](https://github.com/brendangregg/FlameGraph).
+    ///
+    /// Each line of the returned string has the form `comm;root;...;leaf count`, where the frames
+    /// come from `kernel_stack` events (recorded with `trace-cmd record -T` or `--func-stack`) and
+    /// are resolved with [HandleRef::function_name]. Addresses that cannot be resolved are
+    /// printed in hex.
+    pub fn folded_stacks(&mut self) -> Result<String> {
+        let handle = self.handle_ref()?;
+        let long_size = unsafe { bindings::tep_get_long_size(handle.0) } as usize;
+        let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+        try_iterate_events(self, |rec, _cpu| {
+            let event = self.find_event(rec)?;
+            if event.name_str() != "kernel_stack" {
+                return Ok(true);
+            }
+            let callers = event.field_raw(rec, "caller")?;
+
+            let mut frames = vec![];
+            for chunk in callers.chunks_exact(long_size) {
+                let addr = unsafe {
+                    bindings::tep_read_number(
+                        handle.0,
+                        chunk.as_ptr() as *const _,
+                        long_size as i32,
+                    )
+                };
+                // Old kernels pad the array with ULONG_MAX instead of zero.
+                if addr == 0 || addr == u64::MAX || addr == u32::MAX as u64 {
+                    break;
+                }
+                frames.push(
+                    handle
+                        .function_name(addr)
+                        .unwrap_or_else(|| format!("{addr:#x}")),
+                );
+            }
+            if frames.is_empty() {
+                return Ok(true);
+            }
+            frames.push(
+                handle
+                    .comm(handle.pid(rec))
+                    .unwrap_or_else(|| "<...>".to_string()),
+            );
+            frames.reverse();
+
+            *stacks.entry(frames.join(";")).or_default() += 1;
+            Ok(true)
+        })?;
+
+        Ok(stacks
+            .iter()
+            .map(|(stack, count)| format!("{stack} {count}\n"))
+            .collect())
+    }
+}
+
+impl Drop for Input {
+    fn drop(&mut self) {
+        // Safe because `self.handle` must be a valid pointer.
+        unsafe {
+            bindings::tracecmd_close(self.handle);
+        }
+    }
+}
+
+// An `Input` can be moved to another thread because nothing else refers to its handle. The
+// events in the cache point into the parser owned by the handle, so they move together.
+unsafe impl Send for Input {}
+
+/// Shows the path and metadata read from the file header, e.g.
+/// `Input { path: "trace.dat", version: Some(7), cpus: 8, page_size: 4096, clock: Some("local") }`.
+impl std::fmt::Debug for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = self.file_header();
+        f.debug_struct("Input")
+            .field("path", &self.path)
+            .field("version", &header.map(|header| header.version))
+            .field("cpus", &self.header_cpus())
+            .field("page_size", &unsafe {
+                bindings::tep_get_page_size(bindings::tracecmd_get_tep(self.handle))
+            })
+            .field("clock", &header.and_then(|header| header.clock.as_deref()))
+            .finish()
+    }
+}
+
+/// An iterator over the records of a CPU, created by [Input::cpu_records].
+pub struct CpuRecordIter<'a> {
+    input: &'a mut Input,
+    cpu: i32,
+}
+
+impl<'a> Iterator for CpuRecordIter<'a> {
+    type Item = Record<'a>;
+
+    fn next(&mut self) -> Option<Record<'a>> {
+        let rec = unsafe { bindings::tracecmd_read_data(self.input.handle, self.cpu) };
+        if rec.is_null() {
+            return None;
+        }
+        unsafe { self.input.prepare_record(rec) };
+        Some(Record(rec, PhantomData))
+    }
+}
+
+/// An iterator over the records of all CPUs and their events, created by
+/// [Input::decoded_records].
+pub struct DecodedRecordIter<'a> {
+    input: &'a mut Input,
+}
+
+impl<'a> Iterator for DecodedRecordIter<'a> {
+    type Item = Result<(Event, Record<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut cpu = 0;
+        let rec = unsafe { bindings::tracecmd_read_next_data(self.input.handle, &mut cpu) };
+        if rec.is_null() {
+            return None;
+        }
+        unsafe { self.input.prepare_record(rec) };
+        let rec = Record(rec, PhantomData);
+        Some(self.input.find_event(&rec).map(|event| (event, rec)))
+    }
+}
+
+/// A wrapper of
+/// [`tep_handle`](https://www.trace-cmd.org/Documentation/libtraceevent/libtraceevent-handle.html),
+/// the main structure representing the trace event parser context.
+///
+/// The parser is owned by the [Input] that the `HandleRef` is obtained from, so a `HandleRef`
+/// can't outlive the [Input].
+pub struct HandleRef<'a>(*mut bindings::tep_handle, PhantomData<&'a Input>);
+
+impl HandleRef<'_> {
+    /// Gets the raw `tep_handle` pointer, which is owned by the [Input].
+    #[cfg(feature = "raw")]
+    pub fn as_raw(&self) -> *mut bindings::tep_handle {
+        self.0
+    }
+
+    /// Finds the event with the type id `id` without a record.
+    ///
+    /// This is a wrapper of `tep_find_event`. Unlike [Input::find_event], the result is not
+    /// cached, so this is meant for resolving events up front rather than for every record.
+    pub fn find_event(&self, id: i32) -> Result<Event> {
+        let ptr = unsafe { bindings::tep_find_event(self.0, id) };
+        if ptr.is_null() {
+            return Err(Error::FindEvent);
+        }
+        unsafe { Event::from_ptr(ptr) }
+    }
+
+    /// Gets a PID.
+    ///
+    /// This is a wrapper of `tep_data_pid`, which returns `-1` if the PID can't be read (e.g. the
+    /// common fields of the record don't have `common_pid`). Use [Self::try_pid] to get an
+    /// error in that case.
+    pub fn pid(&self, rec: &Record) -> i32 {
+        unsafe { bindings::tep_data_pid(self.0, rec.0) }
+    }
+
+    /// Similar to [Self::pid], but returns [Error::InvalidPid] if the PID can't be read.
+    pub fn try_pid(&self, rec: &Record) -> Result<i32> {
+        match self.pid(rec) {
+            pid if pid < 0 => Err(Error::InvalidPid(format!("tep_data_pid returned {pid}"))),
+            pid => Ok(pid),
+        }
+    }
+
+    /// Checks whether two records were emitted by the same task.
+    ///
+    /// Records are compared by their PIDs, except for PID `0`: each CPU has its own idle task
+    /// (`swapper/N`) and all of them have PID `0`, so records with PID `0` are regarded as from
+    /// the same task only if they were also recorded on the same CPU.
+    pub fn same_task(&self, a: &Record, b: &Record) -> bool {
+        let pid = self.pid(a);
+        if pid != self.pid(b) {
+            return false;
+        }
+        pid != 0 || unsafe { (*a.0).cpu == (*b.0).cpu }
+    }
+
+    /// Gets the command name of a given `pid` from the saved cmdlines, if it was recorded.
+    pub fn comm(&self, pid: i32) -> Option<String> {
+        let ptr = unsafe { bindings::tep_data_comm_from_pid(self.0, pid) };
+        if ptr.is_null() {
+            return None;
+        }
+        unsafe { cptr_to_string(ptr as *mut i8) }.ok()
+    }
+
+    /// Similar to [Self::comm], but replaces invalid UTF-8 sequences with `U+FFFD` instead of
+    /// returning `None`.
+    pub fn comm_lossy(&self, pid: i32) -> Option<String> {
+        let ptr = unsafe { bindings::tep_data_comm_from_pid(self.0, pid) };
+        if ptr.is_null() {
+            return None;
+        }
+        Some(unsafe { cptr_to_string_lossy(ptr) })
+    }
+
+    /// Gets the names of all events known to the parser, keyed by their type ids.
+    pub fn event_id_map(&self) -> HashMap<i32, String> {
+        let mut map = HashMap::new();
+        // The returned array is owned by the parser and terminated with NULL.
+        let mut events = unsafe {
+            bindings::tep_list_events(self.0, bindings::tep_event_sort_type_TEP_EVENT_SORT_ID)
+        };
+        if events.is_null() {
+            return map;
+        }
+        while !unsafe { *events }.is_null() {
+            let event = unsafe { *events };
+            if let Ok(name) = unsafe { cptr_to_string((*event).name) } {
+                map.insert(unsafe { (*event).id }, name);
+            }
+            events = unsafe { events.add(1) };
+        }
+        map
+    }
+
+    /// Loads kernel symbols from a file in the `/proc/kallsyms` format.
+    ///
+    /// This is useful when the trace is analyzed on a different machine and the symbols saved in
+    /// the trace are stale or missing. The loaded symbols are used by [Self::function_name].
+    ///
+    /// This is a wrapper of
+    /// [tep_parse_kallsyms](https://www.trace-cmd.org/Documentation/libtraceevent/libtraceevent-func_apis.html).
+    pub fn load_kallsyms(&mut self, path: &str) -> Result<()> {
+        let kallsyms = std::fs::read(path).map_err(Error::Io)?;
+        let kallsyms = std::ffi::CString::new(kallsyms).map_err(|_| Error::ParseKallsyms)?;
+        let ret = unsafe { bindings::tep_parse_kallsyms(self.0, kallsyms.as_ptr()) };
+        if ret < 0 {
+            return Err(Error::ParseKallsyms);
+        }
+        Ok(())
+    }
+
+    /// Gets the name of the kernel function containing `addr`.
+    ///
+    /// This is a wrapper of
+    /// [tep_find_function](https://www.trace-cmd.org/Documentation/libtraceevent/libtraceevent-func_find.html).
+    pub fn function_name(&self, addr: u64) -> Option<String> {
+        let ptr = unsafe { bindings::tep_find_function(self.0, addr) };
+        if ptr.is_null() {
+            return None;
+        }
+        unsafe { cptr_to_string(ptr as *mut i8) }.ok()
+    }
+
+    /// Similar to [Self::function_name], but replaces invalid UTF-8 sequences with `U+FFFD`
+    /// instead of returning `None`.
+    pub fn function_name_lossy(&self, addr: u64) -> Option<String> {
+        let ptr = unsafe { bindings::tep_find_function(self.0, addr) };
+        if ptr.is_null() {
+            return None;
+        }
+        Some(unsafe { cptr_to_string_lossy(ptr) })
+    }
+}
+
+/// A wrapper of `tep_record`.
+///
+/// Records are reference-counted by libtracecmd. A `Record` owns one reference and releases it
+/// with `tracecmd_free_record` when dropped; the record is freed when the last reference is
+/// released. The references of records given to callbacks are owned by libtracecmd, which
+/// releases them after the callback returns, so use [Record::retain] to keep such a record.
+/// Records obtained otherwise (e.g. from [Input::cpu_records]) are owned by the caller.
+///
+/// A record points into the data of the [Input] it was read from, so it borrows the [Input]
+/// for the lifetime `'a` and can't outlive it.
+pub struct Record<'a>(*mut bindings::tep_record, PhantomData<&'a Input>);
+
+impl Drop for Record<'_> {
+    fn drop(&mut self) {
+        unsafe { bindings::tracecmd_free_record(self.0) }
+    }
+}
+
+impl<'a> Record<'a> {
+    /// Takes a new reference to the record with `tracecmd_record_ref`, so it can be kept after
+    /// the callback that it was given to returns.
+    ///
+    /// The reference is released when the returned `Record` is dropped. The returned `Record`
+    /// borrows the same [Input] as `self`, so it can't outlive the input either.
+    pub fn retain(&self) -> Record<'a> {
+        unsafe { bindings::tracecmd_record_ref(self.0) };
+        Record(self.0, PhantomData)
+    }
+
+    /// Finds the event of the record without going through the cache of an [Input].
+    fn event(&self, handle: &HandleRef) -> Result<Event> {
+        let ptr = unsafe { bindings::tep_find_event_by_record(handle.0, self.0) };
+        if ptr.is_null() {
+            return Err(Error::FindEvent);
+        }
+        unsafe { Event::from_ptr(ptr) }
+    }
+
+    /// Gets a timestamp as stored in the record.
+    ///
+    /// libtracecmd applies the clock corrections of the input that the record was read from
+    /// (the offset added by `trace-cmd report --ts-offset`, TSC-to-nanosecond conversion and the
+    /// host-guest time synchronization) when it reads the record. So, the value seen in
+    /// [Handler::callback] is already corrected in both [Handler::process] and
+    /// [Handler::process_multi], and timestamps of records from different inputs given to
+    /// [Handler::process_multi] are comparable.
+    pub fn ts(&self) -> u64 {
+        unsafe { *self.0 }.ts
+    }
+
+    /// Gets the offset of the record in the file, which identifies the record in the file.
+    ///
+    /// The offset can be kept after the record is freed and passed to [Input::read_at] to read
+    /// the record again.
+    pub fn offset(&self) -> u64 {
+        unsafe { *self.0 }.offset
+    }
+
+    /// Gets the timestamp formatted by [format_ts].
+    pub fn ts_string(&self) -> String {
+        format_ts(self.ts())
+    }
+
+    /// Gets the timestamp relative to the first record of `input`, which is the input that the
+    /// record was read from.
+    ///
+    /// The offset of the input (see [Input::set_ts_offset]) is already applied to [Self::ts] by
+    /// libtracecmd, so it's not applied again here. In [Handler::process] and
+    /// [Handler::process_multi], [Handler::callback] sees the same [Self::ts] as described
+    /// there, but the value of this method is relative to the start of the input of each
+    /// record, so it's not comparable across the inputs of [Handler::process_multi].
+    pub fn ts_relative(&self, input: &Input) -> u64 {
+        let first_ts = unsafe { bindings::tracecmd_get_first_ts(input.handle) };
+        self.ts().saturating_sub(first_ts)
+    }
+
+    /// Gets the number of events the ring buffer dropped right before this record.
+    ///
+    /// `0` means that no event was dropped and `-1` means that some events were dropped but the
+    /// number is unknown.
+    pub fn missed_events(&self) -> i64 {
+        unsafe { *self.0 }.missed_events
+    }
+
+    /// Reads an integer of `size` bytes (1, 2, 4 or 8) at `offset` in the record's data,
+    /// sign-extending it if `signed` is true. Unsigned 64-bit values are returned as their bit
+    /// patterns.
+    ///
+    /// This skips looking up fields by name, so it's useful when the offsets and sizes of
+    /// fields are computed in advance. The integer is read in the byte order of the host, which
+    /// must match the byte order of the traced machine. Returns [Error::ReadField] if the range
+    /// is out of the record or `size` is not supported.
+    pub fn read_u64_at(&self, offset: usize, size: usize, signed: bool) -> Result<i64> {
+        let rec = unsafe { &*self.0 };
+        let data = unsafe { std::slice::from_raw_parts(rec.data as *const u8, rec.size as usize) };
+        let bytes = offset
+            .checked_add(size)
+            .and_then(|end| data.get(offset..end))
+            .ok_or(Error::ReadField)?;
+        Ok(match (size, signed) {
+            (1, false) => bytes[0] as i64,
+            (1, true) => bytes[0] as i8 as i64,
+            (2, false) => u16::from_ne_bytes(bytes.try_into().unwrap()) as i64,
+            (2, true) => i16::from_ne_bytes(bytes.try_into().unwrap()) as i64,
+            (4, false) => u32::from_ne_bytes(bytes.try_into().unwrap()) as i64,
+            (4, true) => i32::from_ne_bytes(bytes.try_into().unwrap()) as i64,
+            (8, _) => i64::from_ne_bytes(bytes.try_into().unwrap()),
+            _ => return Err(Error::ReadField),
+        })
+    }
+
+    /// Gets the command name of the task that emitted the record from the saved cmdlines.
+    ///
+    /// This is a shorthand of [HandleRef::pid] followed by [HandleRef::comm], except for PID
+    /// `0`: each CPU has its own idle task and all of them have PID `0`, so `swapper/<cpu>`
+    /// (e.g. `swapper/3`) is returned with the CPU of the record, as the kernel names them.
+    /// This keeps idle time of different CPUs apart when it's accounted per command name.
+    pub fn comm(&self, handle: &HandleRef) -> Option<String> {
+        match handle.pid(self) {
+            0 => Some(self.idle_comm()),
+            pid => handle.comm(pid),
+        }
+    }
+
+    /// Similar to [Self::comm], but replaces invalid UTF-8 sequences with `U+FFFD`. See
+    /// [HandleRef::comm_lossy].
+    pub fn comm_lossy(&self, handle: &HandleRef) -> Option<String> {
+        match handle.pid(self) {
+            0 => Some(self.idle_comm()),
+            pid => handle.comm_lossy(pid),
+        }
+    }
+
+    /// Gets the name of the idle task of the CPU that the record was recorded on.
+    fn idle_comm(&self) -> String {
+        format!("swapper/{}", unsafe { *self.0 }.cpu)
+    }
+
+    /// Gets the preemption count recorded in the common fields.
+    ///
+    /// This is a wrapper of `tep_data_preempt_count`.
+    pub fn preempt_count(&self, handle: &HandleRef) -> u8 {
+        unsafe { bindings::tep_data_preempt_count(handle.0, self.0) as u8 }
+    }
+
+    /// Gets the big kernel lock depth recorded in the `common_lock_depth` field.
+    ///
+    /// Recent kernels no longer record this field, in which case `-1` is returned.
+    pub fn lock_depth(&self, handle: &HandleRef) -> i32 {
+        let event = unsafe { bindings::tep_find_event_by_record(handle.0, self.0) };
+        if event.is_null() {
+            return -1;
+        }
+        let mut val = 0;
+        let ret = unsafe {
+            bindings::tep_get_common_field_val(
+                std::ptr::null_mut(),
+                event,
+                c"common_lock_depth".as_ptr(),
+                self.0,
+                &mut val,
+                0,
+            )
+        };
+        if ret < 0 {
+            return -1;
+        }
+        val as i32
+    }
+
+    /// Gets the call depth of a `funcgraph_entry` or `funcgraph_exit` record of the function
+    /// graph tracer, which is the indentation `trace-cmd report` uses to render call trees.
+    ///
+    /// Returns `None` if the record is not one of these events.
+    pub fn funcgraph_depth(&self, handle: &HandleRef) -> Option<i32> {
+        let event = unsafe { bindings::tep_find_event_by_record(handle.0, self.0) };
+        if event.is_null() {
+            return None;
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr((*event).name) };
+        if name != c"funcgraph_entry" && name != c"funcgraph_exit" {
+            return None;
+        }
+        let field = unsafe { bindings::tep_find_field(event, c"depth".as_ptr()) };
+        if field.is_null() {
+            return None;
+        }
+        let mut val = 0;
+        let ret = unsafe { bindings::tep_read_number_field(field, (*self.0).data, &mut val) };
+        (ret == 0).then_some(val as i32)
+    }
+
+    /// Renders a `bprint` or `bputs` event written by `trace_printk` into a text.
+    ///
+    /// The format string referenced by the record is resolved from the printk formats saved in
+    /// the trace and the binary arguments are rendered with it, so the result looks like the
+    /// text after `bprint:` in the output of `trace-cmd report` (e.g. `my_func: value=42`).
+    /// The trailing newline is removed.
+    pub fn bprint_text(&self, handle: &HandleRef) -> Result<String> {
+        Ok(self
+            .render_bprint(handle)?
+            .contents()?
+            .trim_end_matches('\n')
+            .to_string())
+    }
+
+    /// Similar to [Self::bprint_text], but replaces invalid UTF-8 sequences in the text with
+    /// `U+FFFD` instead of returning an error.
+    pub fn bprint_text_lossy(&self, handle: &HandleRef) -> Result<String> {
+        Ok(self
+            .render_bprint(handle)?
+            .contents_lossy()
+            .trim_end_matches('\n')
+            .to_string())
+    }
+
+    /// Renders a `bprint` or `bputs` event into a [TraceSeq].
+    fn render_bprint(&self, handle: &HandleRef) -> Result<TraceSeq> {
+        let event = unsafe { bindings::tep_find_event_by_record(handle.0, self.0) };
+        if event.is_null() {
+            return Err(Error::FindEvent);
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr((*event).name) };
+        let flags = EventFlags::from_bits_retain(unsafe { (*event).flags } as u32);
+        if !flags.contains(EventFlags::BPRINT) && name != c"bputs" {
+            return Err(Error::FindEvent);
+        }
+
+        let mut seq = TraceSeq::new();
+        unsafe {
+            bindings::tep_print_event(
+                handle.0,
+                seq.as_mut_ptr(),
+                self.0,
+                c"%s".as_ptr(),
+                bindings::TEP_PRINT_INFO.as_ptr(),
+            );
+        }
+        Ok(seq)
+    }
+}
+
+/// A wrapper of `tep_event`.
+#[derive(Clone)]
+pub struct Event {
+    ptr: *mut bindings::tep_event,
+    /// Name of the event.
+    pub name: String,
+    /// Type id of the event, copied so that an `Event` kept after its [Input] is dropped can
+    /// still be compared and hashed.
+    id: i32,
+    /// Copies of the name and the flags, which can also be used after the [Input] is dropped.
+    name_cstr: std::ffi::CString,
+    flags: EventFlags,
+}
+
+/// Events are compared by their type ids. Note that events of different inputs can have the same
+/// type id if the inputs were recorded on the same kernel.
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for Event {}
+
+impl std::hash::Hash for Event {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of a `tep_event` describing how the parser handles it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct EventFlags: u32 {
+        /// The event is defined by ftrace itself (e.g. `function`, `kernel_stack`).
+        const FTRACE = 0x01;
+        /// The event is a `print` event written by `trace_puts`/`trace_printk` with a string.
+        const PRINT = 0x02;
+        /// The event is a `bprint` event whose arguments are stored in a binary format.
+        const BPRINT = 0x04;
+        /// The event is a function graph entry.
+        const FUNC_ENTRY = 0x10;
+        /// The event is a function graph return.
+        const FUNC_RET = 0x20;
+        /// The event's custom print handler is disabled.
+        const NO_HANDLE = 0x40;
+        /// The event is printed in its raw form.
+        const PRINT_RAW = 0x80;
+        /// The parser failed to parse the event's format.
+        const FAILED = 0x8000_0000;
+    }
+}
+
+impl Event {
+    /// Wraps `ptr`, copying the properties that are kept in the `Event` itself.
+    ///
+    /// `ptr` must be a valid `tep_event`. Fails if the name is not valid UTF-8.
+    unsafe fn from_ptr(ptr: *mut bindings::tep_event) -> Result<Self> {
+        let name = cptr_to_string((*ptr).name)?;
+        Ok(Event {
+            ptr,
+            id: (*ptr).id,
+            name_cstr: std::ffi::CStr::from_ptr((*ptr).name).to_owned(),
+            flags: EventFlags::from_bits_retain((*ptr).flags as u32),
+            name,
+        })
+    }
+
+    /// Gets the type id of the event, which is the value of the `common_type` field of its
+    /// records.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Gets the name of the event as a C string, without allocating.
+    pub fn name_cstr(&self) -> &std::ffi::CStr {
+        &self.name_cstr
+    }
+
+    /// Gets the name of the event without allocating.
+    ///
+    /// Unlike [Event::name], this borrows the string, so it's cheaper in callbacks that only
+    /// compare names.
+    pub fn name_str(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the flags of the event.
+    pub fn flags(&self) -> EventFlags {
+        self.flags
+    }
+
+    /// Prints each field name followed by the record’s field value according to the field’s type.
+    ///
+    /// This is a wrapper of
+    /// [tep_record_print_fields](https://www.trace-cmd.org/Documentation/libtraceevent/libtraceevent-field_print.html).
+    pub fn print_fields(&self, rec: &Record) {
+        let mut seq: bindings::trace_seq = Default::default();
+        unsafe {
+            bindings::trace_seq_init(&mut seq);
+            bindings::trace_seq_reset(&mut seq);
+
+            bindings::tep_record_print_fields(&mut seq, rec.0, self.ptr);
+            bindings::trace_seq_terminate(&mut seq);
+        };
+        let msg = unsafe { std::slice::from_raw_parts(seq.buffer as *mut u8, seq.len as usize) };
+        println!("fields: {:?}", std::str::from_utf8(msg).unwrap());
+    }
+
+    /// Reads the field `name` in `rec` as an unsigned integer.
+    pub fn read_field_u64(&self, rec: &Record, name: &str) -> Result<u64> {
+        let field = self.find_field(name)?;
+        let mut val = 0;
+        let ret = unsafe { bindings::tep_read_number_field(field, (*rec.0).data, &mut val) };
+        if ret != 0 {
+            return Err(Error::ReadField);
+        }
+        Ok(val)
+    }
+
+    /// Reads the field `name` in `rec` as a floating point number.
+    ///
+    /// The raw bytes of the field are reinterpreted (not converted) as an IEEE-754 `double` if
+    /// the field is 8 bytes long, or as a `float` if it's 4 bytes long, as `memcpy` would do in
+    /// C. This is useful for fields declared as integers to which tracepoints bit-cast floating
+    /// point values. Fails with [Error::ReadField] for fields of other sizes.
+    pub fn read_field_f64(&self, rec: &Record, name: &str) -> Result<f64> {
+        let raw = self.field_raw(rec, name)?;
+        match raw.len() {
+            8 => Ok(f64::from_ne_bytes(raw.try_into().unwrap())),
+            4 => Ok(f32::from_ne_bytes(raw.try_into().unwrap()) as f64),
+            _ => Err(Error::ReadField),
+        }
+    }
+
+    /// Reads the numeric field `name` in `rec` and formats it in hex with leading zeros up to the
+    /// size of the field, e.g. `0x00000040` for a 4-byte field.
+    pub fn read_field_hex(&self, rec: &Record, name: &str) -> Result<String> {
+        let size = unsafe { (*self.find_field(name)?).size };
+        let val = self.read_field_u64(rec, name)?;
+        Ok(format!(
+            "{val:#0width$x}",
+            width = 2 + 2 * size.max(0) as usize
+        ))
+    }
+
+    /// Reads the field `name` in `rec` and converts it into the name given by
+    /// `__print_symbolic()` or `__print_flags()` in the event's print format, as `trace-cmd
+    /// report` does (e.g. `GFP_KERNEL|__GFP_ZERO` for `gfp_flags`).
+    ///
+    /// Flags without names and values without symbols are printed in hex. If the print format
+    /// has no mapping for the field, the value is returned in decimal.
+    pub fn read_field_symbolic(&self, rec: &Record, name: &str) -> Result<String> {
+        let val = self.read_field_u64(rec, name)?;
+        Ok(match unsafe { print_fmt::find_mapping(self.ptr, name) } {
+            Some(mapping) => mapping.render(val),
+            None => val.to_string(),
+        })
+    }
+
+    /// Evaluates the arguments of the event's print format for `rec` and returns them in the
+    /// order they appear in the format, as `trace-cmd report` shows them.
+    ///
+    /// Each argument is paired with the name of the field it's computed from (e.g. `prev_state`
+    /// for `__print_flags(REC->prev_state, ...)`), or the argument's expression if there is no
+    /// such field. Values are rendered with the corresponding conversions of the format (e.g.
+    /// `%08lx`, `%pS`), and `__print_symbolic()` and `__print_flags()` are decoded. Arguments
+    /// that this crate can't evaluate, such as calls of helper functions, are rendered as `?`,
+    /// and arguments whose fields can't be read (e.g. a string field printed with `%d`) are
+    /// rendered as `[FAILED TO PARSE]` like libtraceevent does, without failing the others.
+    pub fn print_args(&self, rec: &Record, handle: &HandleRef) -> Result<Vec<(String, String)>> {
+        let number = |name: &str| self.read_field_u64(rec, name);
+        let raw = |name: &str| self.field_raw(rec, name).map(|raw| raw.to_vec());
+        let function = |addr| {
+            let name = handle.function_name(addr)?;
+            let start = unsafe { bindings::tep_find_function_address(handle.0, addr) };
+            Some((name, start))
+        };
+        let ctx = print_fmt::ArgContext {
+            number: &number,
+            raw: &raw,
+            function: &function,
+        };
+        unsafe { print_fmt::print_args(self.ptr, &ctx) }
+    }
+
+    /// Renders the format definition of the event in the same layout as the `format` file in
+    /// tracefs, which `trace-cmd report -E` shows. Field lines are indented and separated with
+    /// tabs, e.g.
+    ///
+    /// ```text
+    /// name: sched_wakeup
+    /// ID: 316
+    /// format:
+    ///     field:unsigned short common_type;    offset:0;    size:2;    signed:0;
+    ///     ...
+    ///
+    ///     field:char comm[16];    offset:8;    size:16;    signed:0;
+    ///     ...
+    ///
+    /// print fmt: "comm=%s pid=%d ...", REC->comm, REC->pid, ...
+    /// ```
+    ///
+    /// This is reconstructed from the format parsed by libtraceevent, so it's suitable for
+    /// comparing formats but the `print fmt:` arguments may not match the original text byte
+    /// for byte.
+    pub fn format_definition(&self) -> String {
+        let format = unsafe { &(*self.ptr).format };
+        let mut def = format!("name: {}\nID: {}\nformat:\n", self.name, self.id());
+        for (i, list) in [format.common_fields, format.fields]
+            .into_iter()
+            .enumerate()
+        {
+            if i > 0 {
+                def.push('\n');
+            }
+            let mut field = list;
+            while !field.is_null() {
+                let f = unsafe { &*field };
+                let type_ = unsafe { cptr_to_string(f.type_) }.unwrap_or_default();
+                let name = unsafe { cptr_to_string(f.name) }.unwrap_or_default();
+                // libtraceevent moves array brackets from the name to the type, e.g. `char[16]`.
+                let decl = match type_.find('[') {
+                    Some(i) if !type_.starts_with("__data_loc") => {
+                        format!("{} {name}{}", &type_[..i], &type_[i..])
+                    }
+                    _ => format!("{type_} {name}"),
+                };
+                let signed = f.flags & bindings::tep_format_flags_TEP_FIELD_IS_SIGNED as u64 != 0;
+                def.push_str(&format!(
+                    "\tfield:{decl};\toffset:{};\tsize:{};\tsigned:{};\n",
+                    f.offset, f.size, signed as u8
+                ));
+                field = f.next;
+            }
+        }
+        def.push('\n');
+        def.push_str(&unsafe { print_fmt::render_print_fmt(self.ptr) });
+        def.push('\n');
+        def
+    }
+
+    /// Computes a hash of the field layout and the print format of the event, which is stable
+    /// across builds and platforms, to detect changes of the tracepoint between traces.
+    ///
+    /// The hash is the 64-bit FNV-1a of [Self::format_definition] without the `ID:` line, so
+    /// the same event recorded on kernels that assigned it different type ids has the same
+    /// hash, while any change of the names, types, offsets, sizes or signedness of the fields
+    /// or of the print format changes the hash.
+    pub fn format_hash(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const PRIME: u64 = 0x100000001b3;
+
+        self.format_definition()
+            .lines()
+            .filter(|line| !line.starts_with("ID: "))
+            .flat_map(|line| line.bytes().chain([b'\n']))
+            .fold(OFFSET_BASIS, |hash, b| {
+                (hash ^ b as u64).wrapping_mul(PRIME)
+            })
+    }
+
+    /// Describes the event as a JSON value with the name, the system, the type id and the fields
+    /// of the event, e.g.
+    ///
+    /// ```text
+    /// {
+    ///   "name": "sched_wakeup",
+    ///   "system": "sched",
+    ///   "id": 316,
+    ///   "fields": [
+    ///     {"name": "common_type", "type": "unsigned short", "offset": 0, "size": 2,
+    ///      "signed": false, "common": true},
+    ///     ...
+    ///     {"name": "comm", "type": "char[16]", "offset": 8, "size": 16, "signed": false,
+    ///      "common": false},
+    ///     ...
+    ///   ]
+    /// }
+    /// ```
+    ///
+    /// Fields are listed in the order of the format, starting with the common fields. Types are
+    /// as parsed by libtraceevent, which moves array brackets from the name to the type.
+    #[cfg(feature = "serde")]
+    pub fn to_json_schema(&self) -> serde_json::Value {
+        let event = unsafe { &*self.ptr };
+        let mut fields = vec![];
+        for (common, list) in [
+            (true, event.format.common_fields),
+            (false, event.format.fields),
+        ] {
+            let mut field = list;
+            while !field.is_null() {
+                let f = unsafe { &*field };
+                let signed = f.flags & bindings::tep_format_flags_TEP_FIELD_IS_SIGNED as u64 != 0;
+                let mut desc = serde_json::Map::new();
+                desc.insert(
+                    "name".into(),
+                    unsafe { cptr_to_string_lossy(f.name) }.into(),
+                );
+                desc.insert(
+                    "type".into(),
+                    unsafe { cptr_to_string_lossy(f.type_) }.into(),
+                );
+                desc.insert("offset".into(), f.offset.into());
+                desc.insert("size".into(), f.size.into());
+                desc.insert("signed".into(), signed.into());
+                desc.insert("common".into(), common.into());
+                fields.push(serde_json::Value::from(desc));
+                field = f.next;
+            }
+        }
+
+        let mut schema = serde_json::Map::new();
+        schema.insert("name".into(), self.name.clone().into());
+        let system =
+            (!event.system.is_null()).then(|| unsafe { cptr_to_string_lossy(event.system) });
+        schema.insert("system".into(), system.into());
+        schema.insert("id".into(), self.id().into());
+        schema.insert("fields".into(), fields.into());
+        schema.into()
+    }
+
+    /// Reads the field `name` in `rec` as a kernel address and resolves it to `symbol+offset`
+    /// (e.g. `do_sys_open+0x1c`), like `%pS` in the kernel.
+    ///
+    /// Addresses that can't be resolved with the kernel symbols known to `handle` are printed in
+    /// hex. See [HandleRef::load_kallsyms] for loading symbols.
+    pub fn read_field_symbol(
+        &self,
+        rec: &Record,
+        name: &str,
+        handle: &HandleRef,
+    ) -> Result<String> {
+        let addr = self.read_field_u64(rec, name)?;
+        Ok(match handle.function_name(addr) {
+            Some(func) => {
+                let start = unsafe { bindings::tep_find_function_address(handle.0, addr) };
+                format!("{func}+{:#x}", addr - start)
+            }
+            None => format!("{addr:#x}"),
+        })
+    }
+
+    /// Checks whether `rec` is a record of this event by comparing the type ids, without looking
+    /// up the event of the record.
+    ///
+    /// `handle` must be of the input that `rec` comes from.
+    pub fn matches(&self, rec: &Record, handle: &HandleRef) -> bool {
+        unsafe { bindings::tep_data_type(handle.0, rec.0) == self.id() }
+    }
+
+    /// Checks whether the event has the field `name`, which can be either a common field or an
+    /// event specific field, without reading any record.
+    pub fn has_field(&self, name: &str) -> bool {
+        self.find_field(name).is_ok()
+    }
+
+    /// Finds the field `name`, which can be either a common field or an event specific field.
+    fn find_field(&self, name: &str) -> Result<*mut bindings::tep_format_field> {
+        let name = std::ffi::CString::new(name).map_err(|_| Error::FindField)?;
+        let field = unsafe { bindings::tep_find_any_field(self.ptr, name.as_ptr()) };
+        if field.is_null() {
+            return Err(Error::FindField);
+        }
+        Ok(field)
+    }
+
+    /// Reads the field `name` in `rec` as a NUL-terminated string, e.g. `char comm[16]`.
+    fn read_field_str(&self, rec: &Record, name: &str) -> Result<String> {
+        let raw = self.field_raw(rec, name)?;
+        let end = raw.iter().position(|&c| c == 0).unwrap_or(raw.len());
+        Ok(String::from_utf8_lossy(&raw[..end]).into_owned())
     }
 
-    /// Gets `Handle` from the `Input`.
-    pub fn handle_ref(&self) -> Result<HandleRef> {
-        let ret = unsafe { bindings::tracecmd_get_tep(self.0) };
-        if ret.is_null() {
-            Err(Error::Handle)
-        } else {
-            Ok(HandleRef(ret))
+    /// Gets the raw bytes of the field `name` in `rec`.
+    fn field_raw<'a>(&self, rec: &'a Record, name: &str) -> Result<&'a [u8]> {
+        let name = std::ffi::CString::new(name).map_err(|_| Error::FindField)?;
+        let mut len = 0;
+        let ptr = unsafe {
+            bindings::tep_get_field_raw(
+                std::ptr::null_mut(),
+                self.ptr,
+                name.as_ptr(),
+                rec.0,
+                &mut len,
+                0,
+            )
+        };
+        if ptr.is_null() {
+            return Err(Error::FindField);
         }
+        Ok(unsafe { std::slice::from_raw_parts(ptr as *const u8, len as usize) })
     }
+}
 
-    /// Gets an `Event` corresponding to a given `rec`.
-    pub fn find_event(&self, rec: &Record) -> Result<Event> {
-        let handle = self.handle_ref()?;
-        let ptr = unsafe { bindings::tep_find_event_by_record(handle.0, rec.0) };
+/// A wrapper of `tep_event_filter`, a compiled event filter.
+///
+/// A filter can be applied to each record in a callback, so multiple filters can be checked
+/// independently for the same record.
+pub struct Filter<'a> {
+    ptr: *mut bindings::tep_event_filter,
+    _handle: PhantomData<HandleRef<'a>>,
+}
+
+impl<'a> Filter<'a> {
+    /// Compiles a filter expression such as `sched_switch: prev_pid == 0`.
+    ///
+    /// See the `-F` option of `trace-cmd report` for the syntax. This is a wrapper of
+    /// [tep_filter_add_filter_str](https://www.trace-cmd.org/Documentation/libtraceevent/libtraceevent-filter.html).
+    pub fn compile(handle: &HandleRef<'a>, expr: &str) -> Result<Self> {
+        let c_expr = std::ffi::CString::new(expr)
+            .map_err(|_| Error::InvalidFilter(format!("{expr:?} contains NUL")))?;
+        let ptr = unsafe { bindings::tep_filter_alloc(handle.0) };
         if ptr.is_null() {
-            return Err(Error::FindEvent);
+            return Err(Error::InvalidFilter(expr.to_string()));
         }
-        let name = unsafe { cptr_to_string((*ptr).name) }.expect("string");
+        let filter = Filter {
+            ptr,
+            _handle: PhantomData,
+        };
+
+        let ret = unsafe { bindings::tep_filter_add_filter_str(filter.ptr, c_expr.as_ptr()) };
+        if ret != bindings::tep_errno_TEP_ERRNO__SUCCESS {
+            let mut buf = [0 as std::os::raw::c_char; 256];
+            unsafe { bindings::tep_filter_strerror(filter.ptr, ret, buf.as_mut_ptr(), buf.len()) };
+            let msg = unsafe { std::ffi::CStr::from_ptr(buf.as_ptr()) };
+            return Err(Error::InvalidFilter(format!(
+                "{expr}: {}",
+                msg.to_string_lossy()
+            )));
+        }
+
+        Ok(filter)
+    }
 
-        Ok(Event { ptr, name })
+    /// Checks whether `rec` matches the filter.
+    pub fn matches(&self, rec: &Record) -> bool {
+        let ret = unsafe { bindings::tep_filter_match(self.ptr, rec.0) };
+        ret == bindings::tep_errno_TEP_ERRNO__FILTER_MATCH
     }
 }
 
-impl Drop for Input {
+impl Drop for Filter<'_> {
     fn drop(&mut self) {
-        // Safe because `self.0` must be a valid pointer.
-        unsafe {
-            bindings::tracecmd_close(self.0);
-        }
+        unsafe { bindings::tep_filter_free(self.ptr) };
     }
 }
 
-/// A wrapper of
-/// [`tep_handle`](https://www.trace-cmd.org/Documentation/libtraceevent/libtraceevent-handle.html),
-/// the main structure representing the trace event parser context.
-pub struct HandleRef(*mut bindings::tep_handle);
+/// An owned `trace_seq` buffer, which is destroyed when dropped.
+struct TraceSeq(bindings::trace_seq);
 
-impl HandleRef {
-    /// Gets a PID.
-    pub fn pid(&self, rec: &Record) -> i32 {
-        unsafe { bindings::tep_data_pid(self.0, rec.0) }
+impl TraceSeq {
+    fn new() -> Self {
+        let mut seq: bindings::trace_seq = Default::default();
+        unsafe { bindings::trace_seq_init(&mut seq) };
+        TraceSeq(seq)
     }
-}
 
-/// A wrapper of `tep_record`.
-pub struct Record(*mut bindings::tep_record);
+    fn as_mut_ptr(&mut self) -> *mut bindings::trace_seq {
+        &mut self.0
+    }
 
-impl Record {
-    /// Gets a timestamp.
-    pub fn ts(&self) -> u64 {
-        unsafe { *self.0 }.ts
+    /// Copies the text written so far.
+    fn contents(&mut self) -> Result<String> {
+        Ok(std::str::from_utf8(self.bytes())
+            .map_err(Error::InvalidString)?
+            .to_string())
     }
-}
 
-/// A wrapper of `tep_event`.
-pub struct Event {
-    ptr: *mut bindings::tep_event,
-    /// Name of the event.
-    pub name: String,
-}
+    /// Similar to [Self::contents], but replaces invalid UTF-8 sequences with `U+FFFD`.
+    fn contents_lossy(&mut self) -> String {
+        String::from_utf8_lossy(self.bytes()).into_owned()
+    }
 
-impl Event {
-    /// Prints each field name followed by the record’s field value according to the field’s type.
-    ///
-    /// This is a wrapper of
-    /// [tep_record_print_fields](https://www.trace-cmd.org/Documentation/libtraceevent/libtraceevent-field_print.html).
-    pub fn print_fields(&self, rec: &Record) {
-        let mut seq: bindings::trace_seq = Default::default();
-        unsafe {
-            bindings::trace_seq_init(&mut seq);
-            bindings::trace_seq_reset(&mut seq);
+    /// Gets the bytes written so far.
+    fn bytes(&mut self) -> &[u8] {
+        unsafe { bindings::trace_seq_terminate(&mut self.0) };
+        unsafe { std::slice::from_raw_parts(self.0.buffer as *const u8, self.0.len as usize) }
+    }
+}
 
-            bindings::tep_record_print_fields(&mut seq, rec.0, self.ptr);
-            bindings::trace_seq_terminate(&mut seq);
-        };
-        let msg = unsafe { std::slice::from_raw_parts(seq.buffer as *mut u8, seq.len as usize) };
-        println!("fields: {:?}", std::str::from_utf8(msg).unwrap());
+impl Drop for TraceSeq {
+    fn drop(&mut self) {
+        unsafe { bindings::trace_seq_destroy(&mut self.0) };
     }
 }
 
@@ -275,6 +1922,14 @@ pub trait Handler {
     type AccumulatedData: Default;
 
     /// A callback that will be called for all events when [Self::process] or [Self::process_multi] is called.
+    ///
+    /// The callback returns [CONTINUE] to go on to the next record, or [STOP] to end the
+    /// iteration early, in which case the data accumulated so far is returned as a success. Any
+    /// other value also ends the iteration and is returned as an error. The same applies to the
+    /// other callbacks and `process_*` methods of this trait.
+    ///
+    /// If the callback panics, the iteration stops and the panic is resumed once libtracecmd
+    /// returns, so the panic doesn't unwind through C code.
     fn callback(
         input: &mut Input,
         rec: &mut Record,
@@ -285,26 +1940,109 @@ pub trait Handler {
     /// Processes the given `input` by calling [Self::callback] for each event and returns
     /// [Self::AccumulatedData] returned by the last call of [Self::callback].
     ///
+    /// Records are passed in timestamp order across all CPUs (see [IterationOrder::TimeSorted]).
+    /// Records with the same timestamp are ordered by CPU.
+    ///
     /// This is a wrapper of [`tracecmd_iterate_events`](https://www.trace-cmd.org/Documentation/libtracecmd/libtracecmd-iterate.html).
     fn process(input: &mut Input) -> std::result::Result<Self::AccumulatedData, i32> {
         let mut data: Self::AccumulatedData = Default::default();
+        iterate_input_cpus(
+            input,
+            None,
+            false,
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                Self::callback(input, rec, cpu, data)
+            }),
+        )?;
+        Ok(data)
+    }
 
-        let ret = unsafe {
-            bindings::tracecmd_iterate_events(
-                input.0,
-                // If `cpus` is null, `cpus` and `cpu_size` are ignored and all of CPUs will be
-                // checked.
-                std::ptr::null_mut(), /* cpus */
-                0,                    /* cpu_size */
-                Some(c_callback::<Self>),
-                &mut data as *mut _ as *mut std::ffi::c_void,
-            )
-        };
-        if ret == 0 {
-            Ok(data)
-        } else {
-            Err(ret)
+    /// A predicate called for each record before [Self::callback] (or the other callbacks of
+    /// this trait) by all `process_*` methods and [Iteration::run]. If it returns `false`, the
+    /// record is skipped without calling the callback, and it doesn't count as a record
+    /// processed by methods like [Self::process_counted] and [Self::process_sample].
+    ///
+    /// This is meant for cheap checks, e.g. of the CPU or the timestamp, that skip records
+    /// before their events are resolved, and complements [Filter] for conditions that filter
+    /// expressions can't express. By default, all records are processed.
+    fn should_process(rec: &Record, cpu: i32) -> bool {
+        let _ = (rec, cpu);
+        true
+    }
+
+    /// A hook called before [Self::callback] (or the other callbacks of this trait) when a record
+    /// comes from a different CPU than the previous processed record, e.g. to flush per-CPU
+    /// state. Like [Self::should_process], it's called by all `process_*` methods and
+    /// [Iteration::run], after [Self::should_process] accepts the record.
+    ///
+    /// It's not called before the first record. In [Self::process_parallel], CPU changes are
+    /// seen per thread, and in the methods taking multiple inputs, only CPU numbers are
+    /// compared. By default, this does nothing.
+    fn on_cpu_change(prev_cpu: i32, new_cpu: i32, data: &mut Self::AccumulatedData) {
+        let _ = (prev_cpu, new_cpu, data);
+    }
+
+    /// Similar to [Self::process], but processes records of different CPUs in parallel and
+    /// merges the results with [Mergeable::merge].
+    ///
+    /// CPUs are distributed among up to [std::thread::available_parallelism] threads, each of
+    /// which processes its CPUs with an input reopened from `input` (see [Input::reopen]). So,
+    /// [Self::callback] sees records of each CPU in timestamp order, but records of different
+    /// CPUs are not ordered with each other, and all records are processed regardless of the
+    /// cursor of `input`.
+    fn process_parallel(input: &Input) -> std::result::Result<Self::AccumulatedData, i32>
+    where
+        Self::AccumulatedData: Mergeable + Send,
+    {
+        let cpus = input.header_cpus().max(1);
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(cpus);
+        let mut jobs = vec![];
+        for i in 0..threads {
+            // Opening an input fails only if the file is gone, which `tracecmd_iterate_events`
+            // reports with -1 as well.
+            let input = input.reopen().map_err(|_| -1)?;
+            let cpus: Vec<i32> = (i..cpus).step_by(threads).map(|cpu| cpu as i32).collect();
+            jobs.push((input, cpus));
+        }
+
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = jobs
+                .into_iter()
+                .map(|(mut input, cpus)| {
+                    scope.spawn(move || {
+                        let mut data: Self::AccumulatedData = Default::default();
+                        iterate_input_cpus(
+                            &mut input,
+                            Some(&cpus),
+                            false,
+                            handler_callback::<Self, _>(
+                                &mut data,
+                                |_index, input, rec, cpu, data| {
+                                    Self::callback(input, rec, cpu, data)
+                                },
+                            ),
+                        )
+                        .map(|()| data)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+                })
+                .collect()
+        });
+
+        let mut data: Self::AccumulatedData = Default::default();
+        for result in results {
+            data.merge(result?);
         }
+        Ok(data)
     }
 
     /// Similar to [Self::process], but can take multiple inputs.
@@ -313,52 +2051,749 @@ pub trait Handler {
     /// This is a wrapper of [`tracecmd_iterate_events`](https://www.trace-cmd.org/Documentation/libtracecmd/libtracecmd-iterate.html).
     fn process_multi(inputs: &mut [Input]) -> std::result::Result<Self::AccumulatedData, i32> {
         let mut data: Self::AccumulatedData = Default::default();
-        let nr_handles = inputs.len() as i32;
+        iterate_inputs(
+            inputs,
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                Self::callback(input, rec, cpu, data)
+            }),
+        )?;
+        Ok(data)
+    }
+
+    /// Similar to [Self::process_multi], but [Self::callback] is called only for records of the
+    /// given CPUs that match the given filter expressions (see [Filter::compile]).
+    ///
+    /// `cpus[i]` and `filters[i]` apply to `inputs[i]`, so a different CPU and filter can be
+    /// chosen for each input (e.g. the vCPU thread on the host and the CPU in the guest).
+    /// `None` or a missing entry means all CPUs or all records. Since libtracecmd can't restrict
+    /// CPUs when merging multiple inputs, records of other CPUs are still read and skipped.
+    ///
+    /// Fails with [Error::InvalidFilter] if a filter doesn't compile for its input, or with
+    /// [Error::Iterate] if the callback returns a value other than [CONTINUE] and [STOP].
+    fn process_multi_filtered(
+        inputs: &mut [Input],
+        cpus: &[Option<&[i32]>],
+        filters: &[Option<&str>],
+    ) -> Result<Self::AccumulatedData> {
+        let mut compiled = vec![];
+        for (i, input) in inputs.iter().enumerate() {
+            let filter = match filters.get(i).copied().flatten() {
+                Some(expr) => {
+                    let tep = unsafe { bindings::tracecmd_get_tep(input.handle) };
+                    if tep.is_null() {
+                        return Err(Error::Handle);
+                    }
+                    // `tep` is owned by `input`, which outlives the filter.
+                    Some(Filter::compile(&HandleRef(tep, PhantomData), expr)?)
+                }
+                None => None,
+            };
+            compiled.push(filter);
+        }
 
-        let mut handles = inputs.iter().map(|input| input.0).collect::<Vec<_>>();
+        let mut data: Self::AccumulatedData = Default::default();
+        iterate_inputs(
+            inputs,
+            handler_callback::<Self, _>(&mut data, |index, input, rec, cpu, data| {
+                if let Some(Some(cpus)) = cpus.get(index) {
+                    if !cpus.contains(&cpu) {
+                        return 0;
+                    }
+                }
+                if compiled[index]
+                    .as_ref()
+                    .is_some_and(|filter| !filter.matches(rec))
+                {
+                    return 0;
+                }
+                Self::callback(input, rec, cpu, data)
+            }),
+        )
+        .map_err(Error::Iterate)?;
+        Ok(data)
+    }
 
-        let ret = unsafe {
-            bindings::tracecmd_iterate_events_multi(
-                handles.as_mut_ptr(),
-                nr_handles,
-                Some(c_callback::<Self>),
-                &mut data as *mut _ as *mut std::ffi::c_void,
-            )
-        };
-        if ret == 0 {
-            Ok(data)
-        } else {
-            Err(ret)
+    /// A callback that will be called for all events when [Self::process_multi_indexed] is called.
+    /// `index` is the index of the input in `inputs` that `rec` comes from.
+    ///
+    /// By default, this calls [Self::callback] ignoring `index`.
+    fn callback_indexed(
+        index: usize,
+        input: &mut Input,
+        rec: &mut Record,
+        cpu: i32,
+        data: &mut Self::AccumulatedData,
+    ) -> i32 {
+        let _ = index;
+        Self::callback(input, rec, cpu, data)
+    }
+
+    /// Similar to [Self::process_multi], but calls [Self::callback_indexed] instead of
+    /// [Self::callback], so the results can be kept per input (e.g. host and guest).
+    fn process_multi_indexed(
+        inputs: &mut [Input],
+    ) -> std::result::Result<Self::AccumulatedData, i32> {
+        let mut data: Self::AccumulatedData = Default::default();
+        iterate_inputs(
+            inputs,
+            handler_callback::<Self, _>(&mut data, |index, input, rec, cpu, data| {
+                Self::callback_indexed(index, input, rec, cpu, data)
+            }),
+        )?;
+        Ok(data)
+    }
+
+    /// A callback that will be called for all events when [Self::process_with_gaps] is called.
+    /// `missed_events` is the number of events that the ring buffer dropped right before `rec`
+    /// (see [Record::missed_events]), so a gap in the data can be marked before handling `rec`.
+    ///
+    /// By default, this calls [Self::callback] ignoring `missed_events`.
+    fn callback_with_gap(
+        input: &mut Input,
+        rec: &mut Record,
+        cpu: i32,
+        missed_events: i64,
+        data: &mut Self::AccumulatedData,
+    ) -> i32 {
+        let _ = missed_events;
+        Self::callback(input, rec, cpu, data)
+    }
+
+    /// Similar to [Self::process], but calls [Self::callback_with_gap] instead of
+    /// [Self::callback].
+    fn process_with_gaps(input: &mut Input) -> std::result::Result<Self::AccumulatedData, i32> {
+        let mut data: Self::AccumulatedData = Default::default();
+        iterate_input_cpus(
+            input,
+            None,
+            false,
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                let missed_events = rec.missed_events();
+                Self::callback_with_gap(input, rec, cpu, missed_events, data)
+            }),
+        )?;
+        Ok(data)
+    }
+
+    /// A callback that will be called for all events when [Self::process_resilient] is called.
+    /// Unlike [Self::callback], it can fail for a record (e.g. when a field can't be read) with
+    /// `Err`, which skips the record without stopping the iteration.
+    ///
+    /// By default, this calls [Self::callback].
+    fn callback_resilient(
+        input: &mut Input,
+        rec: &mut Record,
+        cpu: i32,
+        data: &mut Self::AccumulatedData,
+    ) -> Result<i32> {
+        Ok(Self::callback(input, rec, cpu, data))
+    }
+
+    /// Similar to [Self::process], but calls [Self::callback_resilient] instead of
+    /// [Self::callback] and continues after records for which it returns `Err`. Returns the
+    /// number of such skipped records along with the accumulated data.
+    fn process_resilient(
+        input: &mut Input,
+    ) -> std::result::Result<(Self::AccumulatedData, u64), i32> {
+        let mut data: Self::AccumulatedData = Default::default();
+        let mut skipped = 0;
+        iterate_input_cpus(
+            input,
+            None,
+            false,
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                Self::callback_resilient(input, rec, cpu, data).unwrap_or_else(|_| {
+                    skipped += 1;
+                    0
+                })
+            }),
+        )?;
+        Ok((data, skipped))
+    }
+
+    /// Similar to [Self::process], but passes records to [Self::callback] in the given `order`.
+    ///
+    /// With [IterationOrder::PerCpu], each CPU is iterated in turn, which is useful when the
+    /// state accumulated in [Self::callback] is per CPU.
+    fn process_ordered(
+        input: &mut Input,
+        order: IterationOrder,
+    ) -> std::result::Result<Self::AccumulatedData, i32> {
+        let mut data: Self::AccumulatedData = Default::default();
+        let stopped = std::cell::Cell::new(false);
+        let mut callback =
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                let ret = Self::callback(input, rec, cpu, data);
+                stopped.set(ret == STOP);
+                ret
+            });
+        match order {
+            IterationOrder::TimeSorted => {
+                iterate_input_cpus(input, None, false, &mut callback)?;
+            }
+            IterationOrder::PerCpu => {
+                for cpu in 0..input.header_cpus() as i32 {
+                    iterate_input_cpus(input, Some(&[cpu]), false, &mut callback)?;
+                    if stopped.get() {
+                        break;
+                    }
+                }
+            }
+        }
+        drop(callback);
+        Ok(data)
+    }
+
+    /// Similar to [Self::process], but also returns the number of records passed to
+    /// [Self::callback].
+    fn process_counted(
+        input: &mut Input,
+    ) -> std::result::Result<(Self::AccumulatedData, u64), i32> {
+        let mut data: Self::AccumulatedData = Default::default();
+        let mut count = 0;
+        iterate_input_cpus(
+            input,
+            None,
+            false,
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                count += 1;
+                Self::callback(input, rec, cpu, data)
+            }),
+        )?;
+        Ok((data, count))
+    }
+
+    /// Similar to [Self::process], but [Self::callback] is called only for records of events whose
+    /// names start with `prefix` (e.g. `sys_enter_`).
+    ///
+    /// Whether an event matches is decided once per event type, so names are not compared for
+    /// every record.
+    fn process_prefix(
+        input: &mut Input,
+        prefix: &str,
+    ) -> std::result::Result<Self::AccumulatedData, i32> {
+        let mut data: Self::AccumulatedData = Default::default();
+        let mut matches: HashMap<i32, bool> = HashMap::new();
+        iterate_input_cpus(
+            input,
+            None,
+            false,
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                let id = unsafe {
+                    bindings::tep_data_type(bindings::tracecmd_get_tep(input.handle), rec.0)
+                };
+                let matched = *matches.entry(id).or_insert_with(|| {
+                    input
+                        .find_event(rec)
+                        .is_ok_and(|event| event.name_str().starts_with(prefix))
+                });
+                if !matched {
+                    return 0;
+                }
+                Self::callback(input, rec, cpu, data)
+            }),
+        )?;
+        Ok(data)
+    }
+
+    /// Similar to [Self::process], but [Self::callback] is called only for the first
+    /// `per_cpu_limit` records of each CPU.
+    ///
+    /// This is useful to take a quick look at a huge trace. The iteration stops as soon as every
+    /// CPU reaches the limit, but a CPU that recorded fewer records than the limit makes the
+    /// iteration go through the whole input.
+    fn process_sample(
+        input: &mut Input,
+        per_cpu_limit: usize,
+    ) -> std::result::Result<Self::AccumulatedData, i32> {
+        if per_cpu_limit == 0 {
+            return Ok(Default::default());
+        }
+        let nr_cpus = unsafe { bindings::tep_get_cpus(bindings::tracecmd_get_tep(input.handle)) };
+        let mut counts = vec![0; nr_cpus.max(0) as usize];
+        let mut nr_full_cpus = 0;
+
+        let mut data: Self::AccumulatedData = Default::default();
+        let ret = iterate_input_cpus(
+            input,
+            None,
+            false,
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                let Some(count) = counts.get_mut(cpu as usize) else {
+                    return Self::callback(input, rec, cpu, data);
+                };
+                if *count >= per_cpu_limit {
+                    return 0;
+                }
+                *count += 1;
+                if *count == per_cpu_limit {
+                    nr_full_cpus += 1;
+                }
+
+                let ret = Self::callback(input, rec, cpu, data);
+                if ret == 0 && nr_full_cpus == counts.len() {
+                    // Every CPU reached the limit. Stop iterating.
+                    return STOP;
+                }
+                ret
+            }),
+        );
+        ret?;
+        Ok(data)
+    }
+
+    /// Similar to [Self::process], but [Self::callback] is called only for records whose timestamps
+    /// are in `[start_ts, end_ts]`.
+    ///
+    /// Each CPU is first moved to the ring buffer page that should contain `start_ts` with
+    /// `tracecmd_set_cpu_to_timestamp`, so only the records before `start_ts` in that page are
+    /// read and skipped. The iteration stops at the first record after `end_ts`.
+    fn process_range(
+        input: &mut Input,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> std::result::Result<Self::AccumulatedData, i32> {
+        input.seek_cpus_to(start_ts);
+        let mut data: Self::AccumulatedData = Default::default();
+        let ret = iterate_input_cpus(
+            input,
+            None,
+            false,
+            handler_callback::<Self, _>(&mut data, |_index, input, rec, cpu, data| {
+                let ts = rec.ts();
+                if ts < start_ts {
+                    return 0;
+                }
+                if ts > end_ts {
+                    return STOP;
+                }
+                Self::callback(input, rec, cpu, data)
+            }),
+        );
+        ret?;
+        Ok(data)
+    }
+}
+
+/// Similar to [Handler], but [EventHandler::callback] receives the [Event] of each record.
+///
+/// This saves calling [Input::find_event] at the beginning of every callback. Events are looked
+/// up with the cache of [Input::find_event], and records whose events are unknown to the parser
+/// are skipped.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::collections::HashMap;
+///
+/// use libtracecmd::Event;
+/// use libtracecmd::EventHandler;
+/// use libtracecmd::Input;
+/// use libtracecmd::Record;
+///
+/// struct EventCounter;
+///
+/// impl EventHandler for EventCounter {
+///   type AccumulatedData = HashMap<String, u64>;
+///
+///   fn callback(event: &Event, _rec: &Record, _cpu: i32, data: &mut Self::AccumulatedData) -> i32 {
+///     *data.entry(event.name.clone()).or_default() += 1;
+///     0
+///   }
+/// }
+///
+/// let mut input: Input = Input::new("trace.dat").unwrap();
+/// let counts = EventCounter::process(&mut input).unwrap();
+/// ```
+pub trait EventHandler {
+    /// Type of data passed around among every call of [Self::callback].
+    type AccumulatedData: Default;
+
+    /// A callback that will be called for all events when [Self::process] or [Self::process_multi]
+    /// is called.
+    fn callback(event: &Event, rec: &Record, cpu: i32, data: &mut Self::AccumulatedData) -> i32;
+
+    /// Processes the given `input` by calling [Self::callback] for each event and returns
+    /// [Self::AccumulatedData] returned by the last call of [Self::callback].
+    fn process(input: &mut Input) -> std::result::Result<Self::AccumulatedData, i32> {
+        let mut data: Self::AccumulatedData = Default::default();
+        iterate_input(input, |input, rec, cpu| match input.find_event(rec) {
+            Ok(event) => Self::callback(&event, rec, cpu, &mut data),
+            Err(_) => 0,
+        })?;
+        Ok(data)
+    }
+
+    /// Similar to [Self::process], but can take multiple inputs.
+    fn process_multi(inputs: &mut [Input]) -> std::result::Result<Self::AccumulatedData, i32> {
+        let mut data: Self::AccumulatedData = Default::default();
+        iterate_inputs(inputs, |_index, input, rec, cpu| {
+            match input.find_event(rec) {
+                Ok(event) => Self::callback(&event, rec, cpu, &mut data),
+                Err(_) => 0,
+            }
+        })?;
+        Ok(data)
+    }
+}
+
+/// A value returned from callbacks (e.g. [Handler::callback]) to continue iterating.
+pub const CONTINUE: i32 = 0;
+
+/// A value returned from callbacks (e.g. [Handler::callback]) to stop iterating early without
+/// reporting an error. The iteration returns the data accumulated so far as a success.
+///
+/// Any other non-zero value also stops the iteration, but it's returned as an error.
+pub const STOP: i32 = i32::MIN;
+
+/// A payload of a panic caught in a callback.
+type PanicPayload = Box<dyn std::any::Any + Send>;
+
+/// Calls `f` and returns its result. If `f` panics, the panic is stored in `panic` and [STOP] is
+/// returned instead, since unwinding across the FFI boundary is undefined behavior.
+fn catch_panic(panic: &mut Option<PanicPayload>, f: impl FnOnce() -> i32) -> i32 {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(ret) => ret,
+        Err(payload) => {
+            *panic = Some(payload);
+            STOP
         }
     }
 }
 
-unsafe extern "C" fn c_callback<T: Handler + ?Sized>(
+/// Converts the value returned by libtracecmd's iteration into a `Result`, resuming the panic
+/// caught in a callback if any. Stopping with [STOP] is a success.
+fn finish_iteration(ret: i32, panic: Option<PanicPayload>) -> std::result::Result<(), i32> {
+    if let Some(payload) = panic {
+        std::panic::resume_unwind(payload);
+    }
+    match ret {
+        CONTINUE | STOP => Ok(()),
+        ret => Err(ret),
+    }
+}
+
+/// State shared with [c_callback] while iterating over events.
+struct CallbackContext<'a, F> {
+    /// Inputs being iterated. The one matching the handle given to the callback is passed to `f`.
+    inputs: &'a mut [Input],
+    f: F,
+    panic: Option<PanicPayload>,
+}
+
+unsafe extern "C" fn c_callback<F: FnMut(usize, &mut Input, &mut Record, i32) -> i32>(
     input: *mut bindings::tracecmd_input,
     rec: *mut bindings::tep_record,
     cpu: i32,
     raw_data: *mut std::ffi::c_void,
 ) -> i32 {
-    let mut input = Input(input);
-    let mut rec = Record(rec);
-
-    // TODO: Remove this unnecessary data copy?
-    // What I only need here is a type conversion.
-    let mut data: T::AccumulatedData = Default::default();
-    std::ptr::copy_nonoverlapping(
-        raw_data,
-        &mut data as *mut _ as *mut std::ffi::c_void,
-        std::mem::size_of::<T::AccumulatedData>(),
-    );
-    let res = T::callback(&mut input, &mut rec, cpu, &mut data);
-    std::ptr::copy_nonoverlapping(
-        &mut data as *mut _ as *mut std::ffi::c_void,
-        raw_data,
-        std::mem::size_of::<T::AccumulatedData>(),
-    );
-
-    std::mem::forget(input);
-    std::mem::forget(data);
-
-    res
+    let ctx = &mut *(raw_data as *mut CallbackContext<F>);
+    let inputs = &mut *ctx.inputs;
+    let f = &mut ctx.f;
+    catch_panic(&mut ctx.panic, || {
+        let index = inputs
+            .iter()
+            .position(|i| i.handle == input)
+            .expect("callback called with an unknown input");
+        // This can panic too, e.g. if `f` borrows the caches of the input.
+        inputs[index].prepare_record(rec);
+        // The record is freed by libtracecmd after the callback returns.
+        let mut rec = std::mem::ManuallyDrop::new(Record(rec, PhantomData));
+
+        f(index, &mut inputs[index], &mut rec, cpu)
+    })
+}
+
+/// Gets [c_callback] for the type of the closure in `ctx`, which can't be named.
+fn c_callback_of<F>(_ctx: &CallbackContext<F>) -> TracecmdCallback
+where
+    F: FnMut(usize, &mut Input, &mut Record, i32) -> i32,
+{
+    c_callback::<F>
+}
+
+/// Type of callbacks passed to libtracecmd's iteration functions.
+type TracecmdCallback = unsafe extern "C" fn(
+    *mut bindings::tracecmd_input,
+    *mut bindings::tep_record,
+    i32,
+    *mut std::ffi::c_void,
+) -> i32;
+
+/// Wraps `f`, which handles a record for a method of [Handler] `H`, into the callback given to
+/// [iterate_input_cpus] or [iterate_inputs].
+///
+/// All the methods of [Handler] and [Iteration::run] iterate through this, so that
+/// [Handler::should_process] and [Handler::on_cpu_change] are applied before `f` in the same
+/// way everywhere. `f` receives `data`, which [Handler::on_cpu_change] also updates.
+fn handler_callback<'a, H, F>(
+    data: &'a mut H::AccumulatedData,
+    mut f: F,
+) -> impl FnMut(usize, &mut Input, &mut Record, i32) -> i32 + 'a
+where
+    H: Handler + ?Sized,
+    F: FnMut(usize, &mut Input, &mut Record, i32, &mut H::AccumulatedData) -> i32 + 'a,
+{
+    let mut prev_cpu = None;
+    move |index, input, rec, cpu| {
+        if !H::should_process(rec, cpu) {
+            return CONTINUE;
+        }
+        if let Some(prev_cpu) = prev_cpu.filter(|&prev_cpu| prev_cpu != cpu) {
+            H::on_cpu_change(prev_cpu, cpu, data);
+        }
+        prev_cpu = Some(cpu);
+        f(index, input, rec, cpu, data)
+    }
+}
+
+/// Calls `f` for each record in `input` in timestamp order until `f` returns a non-zero value.
+///
+/// A panic in `f` stops the iteration and is resumed after libtracecmd returns.
+fn iterate_input<F>(input: &mut Input, mut f: F) -> std::result::Result<(), i32>
+where
+    F: FnMut(&mut Input, &mut Record, i32) -> i32,
+{
+    iterate_input_cpus(input, None, false, |_index, input, rec, cpu| {
+        f(input, rec, cpu)
+    })
+}
+
+/// Similar to [iterate_input], but only iterates over records of `cpus` if it's `Some`, and from
+/// the newest record to the oldest one if `reverse` is true. Like [iterate_inputs], `f` also
+/// receives the index of the input, which is always 0.
+fn iterate_input_cpus<F>(
+    input: &mut Input,
+    cpus: Option<&[i32]>,
+    reverse: bool,
+    f: F,
+) -> std::result::Result<(), i32>
+where
+    F: FnMut(usize, &mut Input, &mut Record, i32) -> i32,
+{
+    let mut cpu_set: bindings::cpu_set_t = Default::default();
+    if let Some(cpus) = cpus {
+        let bits_per_word = 8 * std::mem::size_of_val(&cpu_set.__bits[0]);
+        for &cpu in cpus {
+            let cpu = cpu as usize;
+            if let Some(word) = cpu_set.__bits.get_mut(cpu / bits_per_word) {
+                *word |= 1 << (cpu % bits_per_word);
+            }
+        }
+    }
+    let handle = input.handle;
+    let mut ctx = CallbackContext {
+        inputs: std::slice::from_mut(input),
+        f,
+        panic: None,
+    };
+
+    // If `cpus` is null, `cpus` and `cpu_size` are ignored and all of CPUs will be checked.
+    let cpus = match cpus {
+        Some(_) => &mut cpu_set as *mut _,
+        None => std::ptr::null_mut(),
+    };
+    let cpu_size = std::mem::size_of_val(&cpu_set) as i32;
+    let callback = Some(c_callback_of(&ctx));
+    let callback_data = &mut ctx as *mut _ as *mut std::ffi::c_void;
+    let ret = unsafe {
+        if reverse {
+            iterate_events_reverse(handle, cpus, cpu_size, callback, callback_data)
+        } else {
+            bindings::tracecmd_iterate_events(handle, cpus, cpu_size, callback, callback_data)
+        }
+    };
+    finish_iteration(ret, ctx.panic)
+}
+
+/// A wrapper of `tracecmd_iterate_events_reverse`, which is available since libtracecmd 1.4.
+///
+/// Without it, this fails with `-1` without calling `callback`. Callers should check
+/// [supports_iterate_reverse] beforehand to report a better error.
+unsafe fn iterate_events_reverse(
+    handle: *mut bindings::tracecmd_input,
+    cpus: *mut bindings::cpu_set_t,
+    cpu_size: i32,
+    callback: Option<TracecmdCallback>,
+    callback_data: *mut std::ffi::c_void,
+) -> i32 {
+    #[cfg(libtracecmd_iterate_reverse)]
+    {
+        bindings::tracecmd_iterate_events_reverse(
+            handle,
+            cpus,
+            cpu_size,
+            callback,
+            callback_data,
+            false, /* cont */
+        )
+    }
+    #[cfg(not(libtracecmd_iterate_reverse))]
+    {
+        let _ = (handle, cpus, cpu_size, callback, callback_data);
+        -1
+    }
+}
+
+/// Similar to [iterate_input], but iterates over records of multiple inputs. `f` also receives
+/// the index of the input that each record comes from.
+fn iterate_inputs<F>(inputs: &mut [Input], f: F) -> std::result::Result<(), i32>
+where
+    F: FnMut(usize, &mut Input, &mut Record, i32) -> i32,
+{
+    let nr_handles = inputs.len() as i32;
+    let mut handles = inputs.iter().map(|input| input.handle).collect::<Vec<_>>();
+    let mut ctx = CallbackContext {
+        inputs,
+        f,
+        panic: None,
+    };
+
+    let ret = unsafe {
+        bindings::tracecmd_iterate_events_multi(
+            handles.as_mut_ptr(),
+            nr_handles,
+            Some(c_callback::<F>),
+            &mut ctx as *mut _ as *mut std::ffi::c_void,
+        )
+    };
+    finish_iteration(ret, ctx.panic)
+}
+
+/// Gets the number of events dropped right before `rec` for [Input::total_dropped_events]. A gap
+/// whose size is unknown is counted as one.
+fn dropped_events(rec: &Record) -> u64 {
+    match rec.missed_events() {
+        0 => 0,
+        n if n < 0 => 1,
+        n => n as u64,
+    }
+}
+
+/// Counts a record of `cpu` in `counts` for [Input::per_cpu_counts], growing `counts` if needed.
+fn count_cpu(counts: &mut Vec<u64>, cpu: i32) {
+    let cpu = cpu as usize;
+    if cpu >= counts.len() {
+        counts.resize(cpu + 1, 0);
+    }
+    counts[cpu] += 1;
+}
+
+/// Similar to [iterate_events], but for callbacks that can fail. `f` returns `Ok(true)` to
+/// continue and `Ok(false)` to stop iterating. An error returned from `f` stops the iteration
+/// and is returned as is.
+fn try_iterate_events<F>(input: &Input, mut f: F) -> Result<()>
+where
+    F: FnMut(&Record, i32) -> Result<bool>,
+{
+    let mut err = None;
+    let ret = iterate_events(input, |rec, cpu| match f(rec, cpu) {
+        Ok(true) => CONTINUE,
+        Ok(false) => STOP,
+        Err(e) => {
+            err = Some(e);
+            STOP
+        }
+    });
+    if let Some(e) = err {
+        return Err(e);
+    }
+    ret.map_err(Error::Iterate)
+}
+
+/// Calls `f` for each record in `input` in timestamp order until `f` returns a non-zero value.
+///
+/// Unlike [iterate_input], `input` is not passed to `f`, so `f` can borrow it.
+fn iterate_events<F>(input: &Input, f: F) -> std::result::Result<(), i32>
+where
+    F: FnMut(&Record, i32) -> i32,
+{
+    struct Context<'a, F> {
+        input: &'a Input,
+        f: F,
+        panic: Option<PanicPayload>,
+    }
+
+    unsafe extern "C" fn trampoline<F: FnMut(&Record, i32) -> i32>(
+        _input: *mut bindings::tracecmd_input,
+        rec: *mut bindings::tep_record,
+        cpu: i32,
+        raw_data: *mut std::ffi::c_void,
+    ) -> i32 {
+        let ctx = &mut *(raw_data as *mut Context<F>);
+        let input = ctx.input;
+        let f = &mut ctx.f;
+        catch_panic(&mut ctx.panic, || {
+            // This can panic too, e.g. if `f` borrows the caches of `input`.
+            input.prepare_record(rec);
+            let rec = std::mem::ManuallyDrop::new(Record(rec, PhantomData));
+            f(&rec, cpu)
+        })
+    }
+
+    let mut ctx = Context {
+        input,
+        f,
+        panic: None,
+    };
+    let ret = unsafe {
+        bindings::tracecmd_iterate_events(
+            input.handle,
+            std::ptr::null_mut(), /* cpus */
+            0,                    /* cpu_size */
+            Some(trampoline::<F>),
+            &mut ctx as *mut _ as *mut std::ffi::c_void,
+        )
+    };
+    finish_iteration(ret, ctx.panic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_ts_rounds_to_microseconds() {
+        for (ts, expected) in [
+            (0, "0.000000"),
+            (1, "0.000000"),
+            (499, "0.000000"),
+            (500, "0.000001"),
+            (1_000, "0.000001"),
+            (999_999_499, "0.999999"),
+            (999_999_500, "1.000000"),
+            (1_234_567_890_123, "1234.567890"),
+            (1_234_567_890_500, "1234.567891"),
+            (u64::MAX, "18446744073.709552"),
+        ] {
+            assert_eq!(format_ts(ts), expected, "{ts}");
+        }
+    }
+
+    #[test]
+    fn has_function_graph_keeps_cursors() {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "libtracecmd-lib-funcgraph-{}.dat",
+                std::process::id()
+            ))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let mut output = Output::new();
+        output.define_event("marker", &["n"]).unwrap();
+        for n in 0..10 {
+            output.emit("marker", 1_000 + n, &[n]).unwrap();
+        }
+        output.write(&path).unwrap();
+
+        let mut input = Input::new(&path).unwrap();
+        assert!(!input.has_function_graph());
+        let first = input.decoded_records().next().map(|decoded| {
+            let (event, rec) = decoded.unwrap();
+            (rec.ts(), event.read_field_u64(&rec, "n").unwrap())
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(first, Some((1_000, 0)));
+    }
 }
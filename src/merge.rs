@@ -0,0 +1,50 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Merging of data accumulated in parallel.
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+
+/// Data that can be combined with data of the same type accumulated separately, e.g. by
+/// [crate::Handler::process_parallel].
+pub trait Mergeable {
+    /// Merges `other` into `self`.
+    fn merge(&mut self, other: Self);
+}
+
+/// Counts are summed per key.
+impl<K: Eq + Hash, S: BuildHasher> Mergeable for HashMap<K, u64, S> {
+    fn merge(&mut self, other: Self) {
+        for (key, count) in other {
+            *self.entry(key).or_insert(0) += count;
+        }
+    }
+}
+
+macro_rules! impl_mergeable_by_sum {
+    ($($t:ty),*) => {
+        $(
+            /// Values are summed.
+            impl Mergeable for $t {
+                fn merge(&mut self, other: Self) {
+                    *self += other;
+                }
+            }
+        )*
+    };
+}
+
+impl_mergeable_by_sum!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
@@ -0,0 +1,105 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Common information of records.
+
+use crate::bindings;
+use crate::cptr_to_string_lossy;
+use crate::HandleRef;
+use crate::Record;
+
+/// Information common to all records, which reports usually show for each record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordMeta {
+    /// Timestamp of the record. See [Record::ts].
+    pub ts: u64,
+    /// CPU that the record was recorded on.
+    pub cpu: i32,
+    /// PID of the task that emitted the record, or `-1` if it can't be read.
+    pub pid: i32,
+    /// Command name of the task, if it's known. See [Record::comm_lossy], which names the idle
+    /// task of each CPU `swapper/<cpu>`.
+    pub comm: Option<String>,
+    /// Name of the event, or `None` if the event is unknown to the parser.
+    pub event: Option<String>,
+    /// Number of events dropped right before the record. See [Record::missed_events].
+    pub missed_events: i64,
+}
+
+impl Record<'_> {
+    /// Gets the timestamp, CPU, PID, command name, event name and the number of missed events of
+    /// the record at once.
+    ///
+    /// Only the PID, the command name and the event are looked up with libtraceevent, and the
+    /// other values are read from the record directly.
+    pub fn meta(&self, handle: &HandleRef) -> RecordMeta {
+        let rec = unsafe { &*self.0 };
+        let pid = handle.pid(self);
+        let event = unsafe { bindings::tep_find_event_by_record(handle.0, self.0) };
+        RecordMeta {
+            ts: rec.ts,
+            cpu: rec.cpu,
+            pid,
+            comm: self.comm_lossy(handle),
+            event: (!event.is_null()).then(|| unsafe { cptr_to_string_lossy((*event).name) }),
+            missed_events: rec.missed_events,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Handler;
+    use crate::Input;
+    use crate::Output;
+    use crate::STOP;
+
+    #[test]
+    fn meta_comm_of_idle_task() {
+        let path = std::env::temp_dir()
+            .join(format!("libtracecmd-meta-idle-{}.dat", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        // Synthetic events are recorded with PID 0 on CPU 0.
+        let mut output = Output::new();
+        output.define_event("marker", &[]).unwrap();
+        output.emit("marker", 1_000, &[]).unwrap();
+        output.write(&path).unwrap();
+
+        struct FirstMeta;
+        impl Handler for FirstMeta {
+            type AccumulatedData = Option<(RecordMeta, Option<String>)>;
+
+            fn callback(
+                input: &mut Input,
+                rec: &mut Record,
+                _cpu: i32,
+                data: &mut Self::AccumulatedData,
+            ) -> i32 {
+                let handle = input.handle_ref().unwrap();
+                *data = Some((rec.meta(&handle), rec.comm(&handle)));
+                STOP
+            }
+        }
+        let mut input = Input::new(&path).unwrap();
+        let (meta, comm) = FirstMeta::process(&mut input).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(meta.pid, 0);
+        assert_eq!(meta.comm, comm);
+        assert_eq!(meta.comm.as_deref(), Some("swapper/0"));
+    }
+}
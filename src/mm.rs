@@ -0,0 +1,85 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of memory management events.
+
+use crate::Error;
+use crate::HandleRef;
+use crate::Record;
+use crate::Result;
+
+/// A decoded page allocation, page free or user page fault event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageEvent {
+    /// `mm_page_alloc`: pages are allocated.
+    Alloc {
+        /// Page frame number of the first page.
+        pfn: u64,
+        /// Allocation order, i.e. `2^order` pages are allocated.
+        order: u32,
+        /// Raw GFP flags of the allocation.
+        gfp_flags: u64,
+        /// GFP flags decoded with the print format of the event (e.g. `GFP_KERNEL|__GFP_ZERO`).
+        gfp_flags_str: String,
+        /// Migrate type of the pages (e.g. 0 for `MIGRATE_UNMOVABLE`).
+        migratetype: i32,
+    },
+    /// `mm_page_free`: pages are freed.
+    Free {
+        /// Page frame number of the first page.
+        pfn: u64,
+        /// Order of the freed pages.
+        order: u32,
+    },
+    /// `page_fault_user`: a page fault occurs in user space (x86).
+    FaultUser {
+        /// Faulting address.
+        address: u64,
+        /// Instruction pointer at the fault.
+        ip: u64,
+        /// Error code of the fault (e.g. bit 1 set for a write).
+        error_code: u64,
+    },
+}
+
+impl Record<'_> {
+    /// Decodes an `mm_page_alloc`, `mm_page_free` or `page_fault_user` record.
+    ///
+    /// GFP flags are decoded into names with `__print_flags()` in the event's print format, as
+    /// [crate::Event::read_field_symbolic] does. Returns [Error::FindField] if the record is not
+    /// one of these events, or on kernels whose page events don't have the `pfn` field.
+    pub fn page_event(&self, handle: &HandleRef) -> Result<PageEvent> {
+        let event = self.event(handle)?;
+        let number = |name| event.read_field_u64(self, name);
+        Ok(match event.name_str() {
+            "mm_page_alloc" => PageEvent::Alloc {
+                pfn: number("pfn")?,
+                order: number("order")? as u32,
+                gfp_flags: number("gfp_flags")?,
+                gfp_flags_str: event.read_field_symbolic(self, "gfp_flags")?,
+                migratetype: number("migratetype")? as i32,
+            },
+            "mm_page_free" => PageEvent::Free {
+                pfn: number("pfn")?,
+                order: number("order")? as u32,
+            },
+            "page_fault_user" => PageEvent::FaultUser {
+                address: number("address")?,
+                ip: number("ip")?,
+                error_code: number("error_code")?,
+            },
+            _ => return Err(Error::FindField),
+        })
+    }
+}
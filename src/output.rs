@@ -0,0 +1,446 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writing of `trace.dat` files with synthetic events.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+
+use crate::Error;
+use crate::Result;
+
+/// Size of ring buffer pages in the written file.
+const PAGE_SIZE: usize = 4096;
+/// Size of the page header, i.e. the `timestamp` and `commit` fields of `header_page`.
+const PAGE_HEADER_SIZE: usize = 16;
+/// Size of the common fields at the beginning of every event.
+const COMMON_FIELDS_SIZE: usize = 8;
+/// Largest `type_len` of an event header giving the data length in 4-byte words.
+const MAX_SMALL_TYPE_LEN: usize = 28;
+/// `type_len` of an event header extending the time delta of the next event.
+const TYPE_LEN_TIME_EXTEND: u32 = 30;
+/// Number of bits of the time delta in an event header.
+const TIME_DELTA_BITS: u32 = 27;
+/// System of the events defined with [Output::define_event].
+const SYSTEM: &str = "synthetic";
+
+const HEADER_PAGE: &str = "\tfield: u64 timestamp;\toffset:0;\tsize:8;\tsigned:0;
+\tfield: local_t commit;\toffset:8;\tsize:8;\tsigned:1;
+\tfield: int overwrite;\toffset:8;\tsize:1;\tsigned:1;
+\tfield: char data;\toffset:16;\tsize:4080;\tsigned:1;
+";
+
+const HEADER_EVENT: &str = "# compressed entry header
+\ttype_len    :    5 bits
+\ttime_delta  :   27 bits
+\tarray       :   32 bits
+
+\tpadding     : type == 29
+\ttime_extend : type == 30
+\ttime_stamp : type == 31
+\tdata max type_len  == 28
+";
+
+/// An event defined with [Output::define_event].
+struct SyntheticEvent {
+    id: u16,
+    name: String,
+    fields: Vec<String>,
+}
+
+impl SyntheticEvent {
+    /// Gets the format of the event in the same syntax as the `format` files of tracefs.
+    fn format(&self) -> String {
+        let mut format = format!(
+            "name: {}\nID: {}\nformat:\n\
+             \tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;\n\
+             \tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;\n\
+             \tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;\n\
+             \tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;\n\n",
+            self.name, self.id
+        );
+        for (i, field) in self.fields.iter().enumerate() {
+            format += &format!(
+                "\tfield:u64 {field};\toffset:{};\tsize:8;\tsigned:0;\n",
+                COMMON_FIELDS_SIZE + 8 * i
+            );
+        }
+        let fmt: Vec<_> = self.fields.iter().map(|f| format!("{f}=%llu")).collect();
+        let args: Vec<_> = self.fields.iter().map(|f| format!(", REC->{f}")).collect();
+        format += &format!("\nprint fmt: \"{}\"{}\n", fmt.join(" "), args.concat());
+        format
+    }
+}
+
+/// A writer of a `trace.dat` file containing synthetic events, e.g. results of an analysis such
+/// as latencies, so they can be viewed with existing tools such as KernelShark or `trace-cmd
+/// report`.
+///
+/// Events are defined in the `synthetic` system with fields of unsigned 64-bit integers, and
+/// emitted records are kept in memory until [Output::write] writes them as a version 6 file
+/// with a single CPU.
+///
+/// # Example
+///
+/// ```no_run
+/// use libtracecmd::Output;
+///
+/// let mut output = Output::new();
+/// output.define_event("wakeup_latency", &["pid", "latency"]).unwrap();
+/// output.emit("wakeup_latency", 1_000_000, &[42, 1500]).unwrap();
+/// output.write("latency.dat").unwrap();
+/// ```
+#[derive(Default)]
+pub struct Output {
+    events: Vec<SyntheticEvent>,
+    /// Emitted records as timestamps, indices of `events` and values.
+    records: Vec<(u64, usize, Vec<u64>)>,
+}
+
+impl Output {
+    /// Creates an output without events.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Defines an event `name` with the fields `fields`, which hold unsigned 64-bit integers.
+    ///
+    /// Names must be C identifiers, and field names must not start with `common_`, which is
+    /// reserved for the common fields of every event. Fails with
+    /// [Error::InvalidSyntheticEvent] for invalid names or an already defined event.
+    pub fn define_event(&mut self, name: &str, fields: &[&str]) -> Result<()> {
+        let invalid = |msg: String| Err(Error::InvalidSyntheticEvent(msg));
+        if !is_identifier(name) {
+            return invalid(format!("invalid event name {name:?}"));
+        }
+        if self.events.iter().any(|event| event.name == name) {
+            return invalid(format!("{name} is already defined"));
+        }
+        for (i, field) in fields.iter().enumerate() {
+            if !is_identifier(field) || field.starts_with("common_") {
+                return invalid(format!("invalid field name {field:?}"));
+            }
+            if fields[..i].contains(field) {
+                return invalid(format!("duplicate field {field}"));
+            }
+        }
+        // A record must fit in a page with its header, the length word and a time extend.
+        if COMMON_FIELDS_SIZE + 8 * fields.len() + 16 > PAGE_SIZE - PAGE_HEADER_SIZE {
+            return invalid(format!("too many fields for {name}"));
+        }
+        self.events.push(SyntheticEvent {
+            id: self.events.len() as u16 + 1,
+            name: name.to_string(),
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+        });
+        Ok(())
+    }
+
+    /// Emits a record of the event `name` at the timestamp `ts` with `values` of its fields in
+    /// the order of the definition.
+    ///
+    /// Records can be emitted in any order, and are sorted by timestamps when they are written.
+    /// Fails with [Error::FindEvent] if `name` is not defined, or with
+    /// [Error::InvalidSyntheticEvent] if the number of values doesn't match the fields.
+    pub fn emit(&mut self, name: &str, ts: u64, values: &[u64]) -> Result<()> {
+        let index = self
+            .events
+            .iter()
+            .position(|event| event.name == name)
+            .ok_or(Error::FindEvent)?;
+        let nr_fields = self.events[index].fields.len();
+        if values.len() != nr_fields {
+            return Err(Error::InvalidSyntheticEvent(format!(
+                "{name} has {nr_fields} fields, but {} values are given",
+                values.len()
+            )));
+        }
+        self.records.push((ts, index, values.to_vec()));
+        Ok(())
+    }
+
+    /// Writes the events and the records to a new `trace.dat` file at `path`.
+    pub fn write(&self, path: &str) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path).map_err(Error::Io)?);
+        self.write_to(&mut w)?;
+        w.flush().map_err(Error::Io)
+    }
+
+    /// Writes the events and the records in the `trace.dat` format to `w`.
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<()> {
+        let pages = self.pages();
+        let mut header = self.header();
+        header.extend_from_slice(b"flyrecord\0");
+        // The data of the only CPU starts at the next page boundary.
+        let offset = (header.len() + 16).next_multiple_of(PAGE_SIZE);
+        header.extend_from_slice(&(offset as u64).to_ne_bytes());
+        header.extend_from_slice(&(pages.len() as u64).to_ne_bytes());
+        header.resize(offset, 0);
+
+        w.write_all(&header).map_err(Error::Io)?;
+        w.write_all(&pages).map_err(Error::Io)
+    }
+
+    /// Builds the part of the file before the location of the CPU data.
+    fn header(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        buf.extend_from_slice(&[0x17, 0x08, 0x44]);
+        buf.extend_from_slice(b"tracing6\0");
+        buf.push(u8::from(cfg!(target_endian = "big")));
+        buf.push(8); // Size of long.
+        buf.extend_from_slice(&(PAGE_SIZE as u32).to_ne_bytes());
+
+        buf.extend_from_slice(b"header_page\0");
+        buf.extend_from_slice(&(HEADER_PAGE.len() as u64).to_ne_bytes());
+        buf.extend_from_slice(HEADER_PAGE.as_bytes());
+        buf.extend_from_slice(b"header_event\0");
+        buf.extend_from_slice(&(HEADER_EVENT.len() as u64).to_ne_bytes());
+        buf.extend_from_slice(HEADER_EVENT.as_bytes());
+
+        // No ftrace events.
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        // A single system with all events.
+        buf.extend_from_slice(&1u32.to_ne_bytes());
+        buf.extend_from_slice(SYSTEM.as_bytes());
+        buf.push(0);
+        buf.extend_from_slice(&(self.events.len() as u32).to_ne_bytes());
+        for event in &self.events {
+            let format = event.format();
+            buf.extend_from_slice(&(format.len() as u64).to_ne_bytes());
+            buf.extend_from_slice(format.as_bytes());
+        }
+        // No kallsyms, printk formats and cmdlines.
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&0u64.to_ne_bytes());
+        // A single CPU.
+        buf.extend_from_slice(&1u32.to_ne_bytes());
+        buf
+    }
+
+    /// Builds the ring buffer pages with the records sorted by timestamps.
+    fn pages(&self) -> Vec<u8> {
+        let mut records: Vec<_> = self.records.iter().collect();
+        records.sort_by_key(|(ts, _, _)| *ts);
+
+        let mut pages = vec![];
+        let mut page: Option<(u64, Vec<u8>)> = None;
+        let mut last_ts = 0;
+        for (ts, index, values) in records {
+            let mut payload = Vec::with_capacity(COMMON_FIELDS_SIZE + 8 * values.len());
+            payload.extend_from_slice(&self.events[*index].id.to_ne_bytes());
+            payload.extend_from_slice(&[0, 0]); // common_flags and common_preempt_count
+            payload.extend_from_slice(&0i32.to_ne_bytes()); // common_pid
+            for value in values {
+                payload.extend_from_slice(&value.to_ne_bytes());
+            }
+
+            let mut entry = vec![];
+            if let Some((_, data)) = &page {
+                encode_entry(&mut entry, ts - last_ts, &payload);
+                if data.len() + entry.len() > PAGE_SIZE - PAGE_HEADER_SIZE {
+                    finish_page(&mut pages, page.take());
+                }
+            }
+            let (_, data) = page.get_or_insert_with(|| {
+                // The first record of a page has the timestamp of the page.
+                entry.clear();
+                encode_entry(&mut entry, 0, &payload);
+                (*ts, vec![])
+            });
+            data.extend_from_slice(&entry);
+            last_ts = *ts;
+        }
+        finish_page(&mut pages, page);
+        pages
+    }
+}
+
+/// Appends the event header and `payload` of a record whose timestamp is `delta` after the
+/// previous record in the page.
+fn encode_entry(buf: &mut Vec<u8>, mut delta: u64, payload: &[u8]) {
+    let mask = (1 << TIME_DELTA_BITS) - 1;
+    if delta > mask {
+        let extend = (delta >> TIME_DELTA_BITS) as u32;
+        buf.extend_from_slice(&type_len_ts(TYPE_LEN_TIME_EXTEND, (delta & mask) as u32));
+        buf.extend_from_slice(&extend.to_ne_bytes());
+        delta = 0;
+    }
+    // The length of the payload, which is a multiple of 4, is in `type_len` if it's small
+    // enough, or in the following word including the word itself.
+    let len = payload.len();
+    if len <= 4 * MAX_SMALL_TYPE_LEN {
+        buf.extend_from_slice(&type_len_ts((len / 4) as u32, delta as u32));
+    } else {
+        buf.extend_from_slice(&type_len_ts(0, delta as u32));
+        buf.extend_from_slice(&(len as u32 + 4).to_ne_bytes());
+    }
+    buf.extend_from_slice(payload);
+}
+
+/// Encodes the first word of an event header. The order of the bit fields depends on the
+/// endianness, as in the kernel.
+fn type_len_ts(type_len: u32, delta: u32) -> [u8; 4] {
+    let word = if cfg!(target_endian = "big") {
+        (type_len << TIME_DELTA_BITS) | delta
+    } else {
+        type_len | (delta << 5)
+    };
+    word.to_ne_bytes()
+}
+
+/// Appends a page with the timestamp and the data of `page` padded to the page size.
+fn finish_page(pages: &mut Vec<u8>, page: Option<(u64, Vec<u8>)>) {
+    let Some((ts, data)) = page else {
+        return;
+    };
+    let start = pages.len();
+    pages.extend_from_slice(&ts.to_ne_bytes());
+    pages.extend_from_slice(&(data.len() as u64).to_ne_bytes());
+    pages.extend_from_slice(&data);
+    pages.resize(start + PAGE_SIZE, 0);
+}
+
+/// Checks whether `name` is a C identifier.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::FileHeader;
+    use crate::Input;
+
+    /// Gets a path of a temporary file named after `name`.
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!(
+                "libtracecmd-output-{name}-{}.dat",
+                std::process::id()
+            ))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Records as timestamps, event names and values of fields.
+    type Records = Vec<(u64, &'static str, Vec<u64>)>;
+
+    /// Builds an output with records of two events spanning multiple pages, with gaps that need
+    /// time extends. Returns the output and the expected records in timestamp order.
+    fn output() -> (Output, Records) {
+        let mut output = Output::new();
+        output.define_event("latency", &["pid", "ns"]).unwrap();
+        output.define_event("marker", &[]).unwrap();
+        let mut expected = vec![];
+        for i in 0..500 {
+            // Every 100th record is more than 2^27 ns after the previous one.
+            let ts = 1_000_000_000 + i * 1_000 + (i / 100) * 1_000_000_000;
+            expected.push((ts, "latency", vec![i, i * 10]));
+            if i % 50 == 0 {
+                expected.push((ts + 1, "marker", vec![]));
+            }
+        }
+        // Emit in reverse order to check sorting.
+        for (ts, name, values) in expected.iter().rev() {
+            output.emit(name, *ts, values).unwrap();
+        }
+        (output, expected)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (output, expected) = output();
+        let path = temp_path("round-trip");
+        output.write(&path).unwrap();
+
+        let mut input = Input::new(&path).unwrap();
+        let mut records = vec![];
+        for decoded in input.decoded_records() {
+            let (event, rec) = decoded.unwrap();
+            let values = match event.name.as_str() {
+                "latency" => vec![
+                    event.read_field_u64(&rec, "pid").unwrap(),
+                    event.read_field_u64(&rec, "ns").unwrap(),
+                ],
+                _ => vec![],
+            };
+            records.push((rec.ts(), event.name.clone(), values));
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(records.len(), expected.len());
+        for ((ts, name, values), (expected_ts, expected_name, expected_values)) in
+            records.iter().zip(&expected)
+        {
+            assert_eq!(ts, expected_ts);
+            assert_eq!(name, expected_name);
+            assert_eq!(values, expected_values);
+        }
+    }
+
+    #[test]
+    fn header_of_written_file() {
+        let (output, _) = output();
+        let path = temp_path("header");
+        output.write(&path).unwrap();
+        let header = FileHeader::read(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let header = header.unwrap();
+        assert_eq!(header.version, 6);
+        assert_eq!(header.header_page.as_deref(), Some(HEADER_PAGE));
+        assert_eq!(header.header_event.as_deref(), Some(HEADER_EVENT));
+        assert_eq!(header.cpu_data.len(), 1);
+        let size = header.cpu_data[0].size as usize;
+        assert!(size > PAGE_SIZE);
+        assert_eq!(size % PAGE_SIZE, 0);
+        assert_eq!(header.cmdlines.map(|(_, size)| size), Some(0));
+    }
+
+    #[test]
+    fn invalid_events() {
+        let mut output = Output::new();
+        output.define_event("latency", &["pid"]).unwrap();
+        for (name, fields) in [
+            ("latency", &["pid"][..]),
+            ("1latency", &[]),
+            ("wakeup-latency", &[]),
+            ("wakeup", &["common_pid"]),
+            ("wakeup", &["pid", "pid"]),
+        ] {
+            assert!(
+                matches!(
+                    output.define_event(name, fields),
+                    Err(Error::InvalidSyntheticEvent(_))
+                ),
+                "{name} {fields:?}"
+            );
+        }
+        assert!(matches!(
+            output.emit("wakeup", 0, &[]),
+            Err(Error::FindEvent)
+        ));
+        assert!(matches!(
+            output.emit("latency", 0, &[1, 2]),
+            Err(Error::InvalidSyntheticEvent(_))
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pairing of events that enter and exit a section per task.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::try_iterate_events;
+use crate::Input;
+use crate::Result;
+
+/// A pair of an enter event and the exit event matched with it. See [Input::paired_events].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pairing {
+    /// PID of the task that emitted both events.
+    pub pid: i32,
+    /// Timestamp of the enter event.
+    pub enter_ts: u64,
+    /// Timestamp of the exit event.
+    pub exit_ts: u64,
+    /// Time from the enter event to the exit event.
+    pub duration: Duration,
+}
+
+/// Result of [Input::paired_events].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pairings {
+    /// Matched pairs in the order of the exit events.
+    pub pairs: Vec<Pairing>,
+    /// PIDs and timestamps of enter events without matching exit events, in timestamp order.
+    pub unmatched_enters: Vec<(i32, u64)>,
+    /// PIDs and timestamps of exit events without matching enter events, in timestamp order.
+    pub unmatched_exits: Vec<(i32, u64)>,
+}
+
+impl Input {
+    /// Matches records of the event `enter` with records of the event `exit` emitted by the
+    /// same task, e.g. `sys_enter` and `sys_exit`, or `funcgraph_entry` and `funcgraph_exit`.
+    ///
+    /// Enter events are matched in a stack-like manner, so an exit event is paired with the
+    /// last unmatched enter event of the task, which handles nested sections. Tasks are
+    /// identified as in [crate::HandleRef::same_task], so the idle tasks of different CPUs,
+    /// which all have PID `0`, are matched separately. Enter events left at the end of the
+    /// trace (e.g. tasks still in the section) and exit events without preceding enter events
+    /// (e.g. sections entered before tracing started) are reported separately in [Pairings].
+    pub fn paired_events(&mut self, enter: &str, exit: &str) -> Result<Pairings> {
+        let handle = self.handle_ref()?;
+        // Timestamps of unmatched enter events of each task, with the last one on the top. Tasks
+        // are keyed by (pid, cpu), where cpu is -1 except for the idle tasks of PID 0.
+        let mut stacks: HashMap<(i32, i32), Vec<u64>> = HashMap::new();
+        let mut pairings = Pairings::default();
+        try_iterate_events(self, |rec, cpu| {
+            let event = self.find_event(rec)?;
+            let name = event.name_str();
+            let is_enter = name == enter;
+            if !is_enter && name != exit {
+                return Ok(true);
+            }
+            let pid = handle.pid(rec);
+            let ts = rec.ts();
+            let stack = stacks
+                .entry((pid, if pid == 0 { cpu } else { -1 }))
+                .or_default();
+            if is_enter {
+                stack.push(ts);
+            } else if let Some(enter_ts) = stack.pop() {
+                pairings.pairs.push(Pairing {
+                    pid,
+                    enter_ts,
+                    exit_ts: ts,
+                    duration: Duration::from_nanos(ts.saturating_sub(enter_ts)),
+                });
+            } else {
+                pairings.unmatched_exits.push((pid, ts));
+            }
+            Ok(true)
+        })?;
+
+        pairings.unmatched_enters = stacks
+            .into_iter()
+            .flat_map(|((pid, _), stack)| stack.into_iter().map(move |ts| (pid, ts)))
+            .collect();
+        pairings.unmatched_enters.sort_by_key(|&(_, ts)| ts);
+        Ok(pairings)
+    }
+}
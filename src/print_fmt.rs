@@ -0,0 +1,886 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers to interpret the print formats (`print fmt:` in the event format files) parsed by
+//! libtraceevent.
+
+use crate::bindings;
+use crate::cptr_to_string;
+use crate::Result;
+
+/// A `__print_symbolic()` or `__print_flags()` mapping from values of a field to names.
+pub(crate) enum Mapping {
+    /// `__print_symbolic()`, which maps each value to a name.
+    Symbolic(Vec<(u64, String)>),
+    /// `__print_flags()`, which maps each bit (or set of bits) to a name.
+    Flags {
+        delim: String,
+        flags: Vec<(u64, String)>,
+    },
+}
+
+impl Mapping {
+    /// Renders `val` in the same way as libtraceevent does.
+    pub(crate) fn render(&self, val: u64) -> String {
+        match self {
+            Mapping::Symbolic(symbols) => symbols
+                .iter()
+                .find(|(v, _)| *v == val)
+                .map(|(_, name)| name.clone())
+                .unwrap_or_else(|| format!("{val:#x}")),
+            Mapping::Flags { delim, flags } => {
+                let mut names = vec![];
+                let mut rest = val;
+                for (fval, name) in flags {
+                    if rest == 0 && *fval == 0 {
+                        names.push(name.clone());
+                        break;
+                    }
+                    if *fval != 0 && rest & fval == *fval {
+                        names.push(name.clone());
+                        rest &= !fval;
+                    }
+                }
+                if rest != 0 {
+                    names.push(format!("{rest:#x}"));
+                }
+                names.join(delim)
+            }
+        }
+    }
+}
+
+/// Parses a value of `__print_symbolic()` or `__print_flags()`, which libtraceevent has already
+/// evaluated into a number.
+fn parse_value(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        return u64::from_str_radix(hex, 16).ok();
+    }
+    value
+        .parse::<u64>()
+        .ok()
+        .or_else(|| value.parse::<i64>().ok().map(|v| v as u64))
+}
+
+/// Converts a list of `tep_print_flag_sym` into pairs of values and names.
+unsafe fn flag_syms(mut sym: *mut bindings::tep_print_flag_sym) -> Vec<(u64, String)> {
+    let mut syms = vec![];
+    while !sym.is_null() {
+        if let (Some(val), Ok(name)) = (
+            cptr_to_string((*sym).value)
+                .ok()
+                .and_then(|v| parse_value(&v)),
+            cptr_to_string((*sym).str),
+        ) {
+            syms.push((val, name));
+        }
+        sym = (*sym).next;
+    }
+    syms
+}
+
+/// Gets the name of the field that `arg` refers to, looking through type casts.
+unsafe fn field_name(arg: *mut bindings::tep_print_arg) -> Option<String> {
+    if arg.is_null() {
+        return None;
+    }
+    match (*arg).type_ {
+        bindings::tep_print_arg_type_TEP_PRINT_FIELD => {
+            cptr_to_string((*arg).__bindgen_anon_1.field.name).ok()
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_TYPE => {
+            field_name((*arg).__bindgen_anon_1.typecast.item)
+        }
+        _ => None,
+    }
+}
+
+/// Searches `arg` and its children for a mapping applied to the field `name`.
+unsafe fn find_in_arg(arg: *mut bindings::tep_print_arg, name: &str) -> Option<Mapping> {
+    if arg.is_null() {
+        return None;
+    }
+    let anon = &(*arg).__bindgen_anon_1;
+    match (*arg).type_ {
+        bindings::tep_print_arg_type_TEP_PRINT_SYMBOL => {
+            if field_name(anon.symbol.field).as_deref() == Some(name) {
+                return Some(Mapping::Symbolic(flag_syms(anon.symbol.symbols)));
+            }
+            None
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_FLAGS => {
+            if field_name(anon.flags.field).as_deref() == Some(name) {
+                let delim = if anon.flags.delim.is_null() {
+                    String::new()
+                } else {
+                    cptr_to_string(anon.flags.delim).unwrap_or_default()
+                };
+                return Some(Mapping::Flags {
+                    delim,
+                    flags: flag_syms(anon.flags.flags),
+                });
+            }
+            None
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_TYPE => find_in_arg(anon.typecast.item, name),
+        bindings::tep_print_arg_type_TEP_PRINT_OP => {
+            find_in_arg(anon.op.left, name).or_else(|| find_in_arg(anon.op.right, name))
+        }
+        _ => None,
+    }
+}
+
+/// Finds the `__print_symbolic()` or `__print_flags()` mapping applied to the field `name` in the
+/// print format of `event`.
+///
+/// # Safety
+///
+/// `event` must be a valid pointer.
+pub(crate) unsafe fn find_mapping(event: *mut bindings::tep_event, name: &str) -> Option<Mapping> {
+    let mut arg = (*event).print_fmt.args;
+    while !arg.is_null() {
+        if let Some(mapping) = find_in_arg(arg, name) {
+            return Some(mapping);
+        }
+        arg = (*arg).next;
+    }
+    None
+}
+
+/// Renders `arg` back into the C-like expression it was parsed from.
+unsafe fn render_arg(arg: *mut bindings::tep_print_arg) -> String {
+    if arg.is_null() {
+        return String::new();
+    }
+    let anon = &(*arg).__bindgen_anon_1;
+    let string = |ptr| cptr_to_string(ptr).unwrap_or_default();
+    let syms = |sym| {
+        flag_syms(sym)
+            .iter()
+            .map(|(val, name)| format!("{{ {val:#x}, \"{name}\" }}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match (*arg).type_ {
+        bindings::tep_print_arg_type_TEP_PRINT_ATOM => string(anon.atom.atom),
+        bindings::tep_print_arg_type_TEP_PRINT_FIELD => {
+            format!("REC->{}", string(anon.field.name))
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_FLAGS => {
+            let delim = if anon.flags.delim.is_null() {
+                String::new()
+            } else {
+                string(anon.flags.delim)
+            };
+            format!(
+                "__print_flags({}, \"{delim}\", {})",
+                render_arg(anon.flags.field),
+                syms(anon.flags.flags)
+            )
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_SYMBOL => format!(
+            "__print_symbolic({}, {})",
+            render_arg(anon.symbol.field),
+            syms(anon.symbol.symbols)
+        ),
+        bindings::tep_print_arg_type_TEP_PRINT_HEX => format!(
+            "__print_hex({}, {})",
+            render_arg(anon.hex.field),
+            render_arg(anon.hex.size)
+        ),
+        bindings::tep_print_arg_type_TEP_PRINT_INT_ARRAY => format!(
+            "__print_array({}, {}, {})",
+            render_arg(anon.int_array.field),
+            render_arg(anon.int_array.count),
+            render_arg(anon.int_array.el_size)
+        ),
+        bindings::tep_print_arg_type_TEP_PRINT_TYPE => format!(
+            "({}){}",
+            string(anon.typecast.type_),
+            render_arg(anon.typecast.item)
+        ),
+        bindings::tep_print_arg_type_TEP_PRINT_STRING
+        | bindings::tep_print_arg_type_TEP_PRINT_BSTRING => {
+            format!("__get_str({})", string(anon.string.string))
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_DYNAMIC_ARRAY => {
+            let field = anon.dynarray.field;
+            let name = if field.is_null() {
+                String::new()
+            } else {
+                string((*field).name)
+            };
+            format!("__get_dynamic_array({name})")
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_BITMASK => {
+            format!("__get_bitmask({})", string(anon.bitmask.bitmask))
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_OP => {
+            let op = string(anon.op.op);
+            if anon.op.left.is_null() {
+                format!("{op}{}", render_arg(anon.op.right))
+            } else if op == "[" {
+                format!(
+                    "{}[{}]",
+                    render_arg(anon.op.left),
+                    render_arg(anon.op.right)
+                )
+            } else {
+                format!(
+                    "({} {op} {})",
+                    render_arg(anon.op.left),
+                    render_arg(anon.op.right)
+                )
+            }
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_FUNC => {
+            // The name of the function is private to libtraceevent.
+            let mut args = vec![];
+            let mut item = anon.func.args;
+            while !item.is_null() {
+                args.push(render_arg(item));
+                item = (*item).next;
+            }
+            format!("<function>({})", args.join(", "))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Renders the `print fmt:` line of the format file of `event`.
+///
+/// The arguments are rendered from their parsed form, so parentheses and spacing can differ
+/// from the original text.
+///
+/// # Safety
+///
+/// `event` must be a valid pointer.
+pub(crate) unsafe fn render_print_fmt(event: *mut bindings::tep_event) -> String {
+    let print_fmt = &(*event).print_fmt;
+    let mut line = format!(
+        "print fmt: \"{}\"",
+        cptr_to_string(print_fmt.format).unwrap_or_default()
+    );
+    let mut arg = print_fmt.args;
+    while !arg.is_null() {
+        line.push_str(", ");
+        line.push_str(&render_arg(arg));
+        arg = (*arg).next;
+    }
+    line
+}
+
+/// A conversion specification (e.g. `%08lx`) in a print format.
+struct Conversion {
+    /// The `#` flag.
+    alt: bool,
+    /// The `0` flag.
+    zero: bool,
+    /// The `-` flag.
+    left: bool,
+    width: usize,
+    /// The precision after `.`, e.g. `3` of `%.3s`. `.` alone is the precision 0.
+    precision: Option<usize>,
+    /// The `l` or `ll` length modifier.
+    long: bool,
+    /// The conversion character, or `*` for an argument giving the width of the next
+    /// conversion and `.` for one giving its precision.
+    conv: char,
+    /// The extension of `%p` (e.g. `S` of `%pS`).
+    ext: Option<char>,
+}
+
+impl Conversion {
+    /// Pads `s` to the width of the conversion. Strings of `%s` are truncated to the precision
+    /// first.
+    fn pad(&self, s: String) -> String {
+        let s = match self.precision {
+            Some(precision) if self.conv == 's' => s.chars().take(precision).collect(),
+            _ => s,
+        };
+        let len = s.chars().count();
+        if len >= self.width {
+            return s;
+        }
+        let fill = self.width - len;
+        if self.left {
+            format!("{s}{}", " ".repeat(fill))
+        } else if self.zero && self.precision.is_none() && self.conv != 's' {
+            // Zeros go after the sign and the `0x` prefix.
+            let prefix_len = number_prefix_len(&s);
+            format!(
+                "{}{}{}",
+                &s[..prefix_len],
+                "0".repeat(fill),
+                &s[prefix_len..]
+            )
+        } else {
+            format!("{}{s}", " ".repeat(fill))
+        }
+    }
+
+    /// Renders an integer value.
+    fn render_number(&self, val: u64) -> String {
+        let val32 = if self.long { val } else { val as u32 as u64 };
+        let s = match self.conv {
+            'd' | 'i' if self.long => (val as i64).to_string(),
+            'd' | 'i' => (val as i32).to_string(),
+            'u' => val32.to_string(),
+            'x' if self.alt => format!("{val32:#x}"),
+            'x' => format!("{val32:x}"),
+            'X' if self.alt => format!("0X{val32:X}"),
+            'X' => format!("{val32:X}"),
+            'o' => format!("{val32:o}"),
+            'c' => return self.pad((val as u8 as char).to_string()),
+            _ => format!("{val:#x}"),
+        };
+        // The precision is the minimum number of digits, and the value 0 with the precision 0
+        // has no digits.
+        let s = match self.precision {
+            Some(precision) => {
+                let (prefix, digits) = s.split_at(number_prefix_len(&s));
+                let digits = if precision == 0 && val32 == 0 {
+                    ""
+                } else {
+                    digits
+                };
+                format!("{prefix}{digits:0>precision$}")
+            }
+            None => s,
+        };
+        self.pad(s)
+    }
+}
+
+/// Gets the length of the sign or the `0x` prefix of a rendered number.
+fn number_prefix_len(s: &str) -> usize {
+    if s.starts_with('-') {
+        1
+    } else if s.starts_with("0x") || s.starts_with("0X") {
+        2
+    } else {
+        0
+    }
+}
+
+/// Parses the conversion specifications in a print format, in order.
+fn conversions(fmt: &str) -> Vec<Conversion> {
+    let mut convs = vec![];
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            continue;
+        }
+        let mut conv = Conversion {
+            alt: false,
+            zero: false,
+            left: false,
+            width: 0,
+            precision: None,
+            long: false,
+            conv: 'd',
+            ext: None,
+        };
+        while let Some(&c) = chars.peek() {
+            match c {
+                '#' => conv.alt = true,
+                '0' => conv.zero = true,
+                '-' => conv.left = true,
+                ' ' | '+' => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        // Arguments giving the width and the precision come before the value.
+        let mut stars = vec![];
+        while let Some(&c) = chars.peek() {
+            match (c, &mut conv.precision) {
+                ('0'..='9', None) => {
+                    conv.width = conv.width * 10 + c.to_digit(10).unwrap() as usize
+                }
+                ('0'..='9', Some(precision)) => {
+                    *precision = *precision * 10 + c.to_digit(10).unwrap() as usize
+                }
+                ('.', _) => conv.precision = Some(0),
+                ('*', None) => stars.push('*'),
+                ('*', Some(_)) => stars.push('.'),
+                _ => break,
+            }
+            chars.next();
+        }
+        while let Some(&c) = chars.peek() {
+            match c {
+                'l' | 'z' | 'j' | 't' | 'L' | 'q' => conv.long = true,
+                'h' => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        for star in stars {
+            convs.push(Conversion { conv: star, ..conv });
+        }
+        conv.conv = chars.next().unwrap_or('d');
+        if conv.conv == 'p' {
+            conv.long = true;
+            if chars.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                conv.ext = chars.next();
+                while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    chars.next();
+                }
+            }
+        }
+        convs.push(conv);
+    }
+    convs
+}
+
+/// Access to a record and the parser, used to evaluate print arguments.
+pub(crate) struct ArgContext<'a> {
+    /// Reads a field as an integer.
+    pub(crate) number: &'a dyn Fn(&str) -> Result<u64>,
+    /// Reads the raw bytes of a field, resolving dynamic fields.
+    pub(crate) raw: &'a dyn Fn(&str) -> Result<Vec<u8>>,
+    /// Resolves a kernel address into the name and the start address of its function.
+    pub(crate) function: &'a dyn Fn(u64) -> Option<(String, u64)>,
+}
+
+/// Evaluates `arg` into an integer. Returns `Ok(None)` if `arg` is not supported.
+unsafe fn eval(arg: *mut bindings::tep_print_arg, ctx: &ArgContext) -> Result<Option<u64>> {
+    if arg.is_null() {
+        return Ok(None);
+    }
+    let anon = &(*arg).__bindgen_anon_1;
+    Ok(match (*arg).type_ {
+        bindings::tep_print_arg_type_TEP_PRINT_ATOM => cptr_to_string(anon.atom.atom)
+            .ok()
+            .and_then(|v| parse_value(&v)),
+        bindings::tep_print_arg_type_TEP_PRINT_FIELD => {
+            Some((ctx.number)(&cptr_to_string(anon.field.name)?)?)
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_TYPE => eval(anon.typecast.item, ctx)?,
+        bindings::tep_print_arg_type_TEP_PRINT_OP => {
+            let op = cptr_to_string(anon.op.op)?;
+            if anon.op.left.is_null() {
+                let Some(val) = eval(anon.op.right, ctx)? else {
+                    return Ok(None);
+                };
+                return Ok(match op.as_str() {
+                    "!" => Some((val == 0) as u64),
+                    "~" => Some(!val),
+                    "-" => Some(val.wrapping_neg()),
+                    "+" => Some(val),
+                    _ => None,
+                });
+            }
+            if op == "?" {
+                // The right operand is the `:` operation with both branches.
+                let right = anon.op.right;
+                if right.is_null() || (*right).type_ != bindings::tep_print_arg_type_TEP_PRINT_OP {
+                    return Ok(None);
+                }
+                let branches = &(*right).__bindgen_anon_1.op;
+                let Some(cond) = eval(anon.op.left, ctx)? else {
+                    return Ok(None);
+                };
+                return eval(
+                    if cond != 0 {
+                        branches.left
+                    } else {
+                        branches.right
+                    },
+                    ctx,
+                );
+            }
+            let (Some(l), Some(r)) = (eval(anon.op.left, ctx)?, eval(anon.op.right, ctx)?) else {
+                return Ok(None);
+            };
+            match op.as_str() {
+                "+" => Some(l.wrapping_add(r)),
+                "-" => Some(l.wrapping_sub(r)),
+                "*" => Some(l.wrapping_mul(r)),
+                "/" => l.checked_div(r),
+                "%" => l.checked_rem(r),
+                "&" => Some(l & r),
+                "|" => Some(l | r),
+                "^" => Some(l ^ r),
+                "<<" => Some(l.wrapping_shl(r as u32)),
+                ">>" => Some(l.wrapping_shr(r as u32)),
+                "==" => Some((l == r) as u64),
+                "!=" => Some((l != r) as u64),
+                "<" => Some((l < r) as u64),
+                ">" => Some((l > r) as u64),
+                "<=" => Some((l <= r) as u64),
+                ">=" => Some((l >= r) as u64),
+                "&&" => Some((l != 0 && r != 0) as u64),
+                "||" => Some((l != 0 || r != 0) as u64),
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+/// Converts raw bytes of a string field into a string, stopping at the first NUL.
+fn raw_string(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&c| c == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).into_owned()
+}
+
+/// Renders the value of `arg` for the conversion `conv`. Returns `Ok(None)` if `arg` is not
+/// supported.
+unsafe fn render_value(
+    arg: *mut bindings::tep_print_arg,
+    conv: &Conversion,
+    ctx: &ArgContext,
+) -> Result<Option<String>> {
+    let anon = &(*arg).__bindgen_anon_1;
+    match (*arg).type_ {
+        bindings::tep_print_arg_type_TEP_PRINT_FIELD if conv.conv == 's' => {
+            let raw = (ctx.raw)(&cptr_to_string(anon.field.name)?)?;
+            return Ok(Some(conv.pad(raw_string(&raw))));
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_STRING
+        | bindings::tep_print_arg_type_TEP_PRINT_BSTRING => {
+            let raw = (ctx.raw)(&cptr_to_string(anon.string.string)?)?;
+            return Ok(Some(conv.pad(raw_string(&raw))));
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_SYMBOL => {
+            let Some(val) = eval(anon.symbol.field, ctx)? else {
+                return Ok(None);
+            };
+            let mapping = Mapping::Symbolic(flag_syms(anon.symbol.symbols));
+            return Ok(Some(conv.pad(mapping.render(val))));
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_FLAGS => {
+            let Some(val) = eval(anon.flags.field, ctx)? else {
+                return Ok(None);
+            };
+            let delim = if anon.flags.delim.is_null() {
+                String::new()
+            } else {
+                cptr_to_string(anon.flags.delim).unwrap_or_default()
+            };
+            let mapping = Mapping::Flags {
+                delim,
+                flags: flag_syms(anon.flags.flags),
+            };
+            return Ok(Some(conv.pad(mapping.render(val))));
+        }
+        bindings::tep_print_arg_type_TEP_PRINT_HEX => {
+            let Some(name) = field_name(anon.hex.field) else {
+                return Ok(None);
+            };
+            let raw = (ctx.raw)(&name)?;
+            let len =
+                eval(anon.hex.size, ctx)?.map_or(raw.len(), |len| raw.len().min(len as usize));
+            let bytes: Vec<String> = raw[..len].iter().map(|b| format!("{b:02x}")).collect();
+            return Ok(Some(bytes.join(" ")));
+        }
+        _ => {}
+    }
+
+    let Some(val) = eval(arg, ctx)? else {
+        return Ok(None);
+    };
+    if conv.conv != 'p' {
+        return Ok(Some(conv.render_number(val)));
+    }
+    let symbol = match conv.ext {
+        Some('S' | 'F' | 'B') => (ctx.function)(val)
+            .map(|(name, start)| format!("{name}+{:#x}", val.wrapping_sub(start))),
+        Some('s' | 'f') => (ctx.function)(val).map(|(name, _)| name),
+        _ => None,
+    };
+    Ok(Some(
+        conv.pad(symbol.unwrap_or_else(|| format!("{val:#x}"))),
+    ))
+}
+
+/// Gets the name of the field that `arg` is mainly about, or renders `arg` if there is none.
+unsafe fn arg_name(arg: *mut bindings::tep_print_arg) -> String {
+    let anon = &(*arg).__bindgen_anon_1;
+    let name = match (*arg).type_ {
+        bindings::tep_print_arg_type_TEP_PRINT_SYMBOL => field_name(anon.symbol.field),
+        bindings::tep_print_arg_type_TEP_PRINT_FLAGS => field_name(anon.flags.field),
+        bindings::tep_print_arg_type_TEP_PRINT_HEX => field_name(anon.hex.field),
+        bindings::tep_print_arg_type_TEP_PRINT_STRING
+        | bindings::tep_print_arg_type_TEP_PRINT_BSTRING => cptr_to_string(anon.string.string).ok(),
+        _ => field_name(arg),
+    };
+    name.unwrap_or_else(|| render_arg(arg))
+}
+
+/// The value of an argument whose fields can't be read, as libtraceevent prints it.
+const FAILED_TO_PARSE: &str = "[FAILED TO PARSE]";
+
+/// Evaluates the arguments of the print format of `event` for a record and renders them with
+/// the corresponding conversions of the format, in order. Arguments that fail to be evaluated
+/// are rendered as [FAILED_TO_PARSE].
+///
+/// # Safety
+///
+/// `event` must be a valid pointer.
+pub(crate) unsafe fn print_args(
+    event: *mut bindings::tep_event,
+    ctx: &ArgContext,
+) -> Result<Vec<(String, String)>> {
+    let print_fmt = &(*event).print_fmt;
+    let fmt = if print_fmt.format.is_null() {
+        String::new()
+    } else {
+        cptr_to_string(print_fmt.format)?
+    };
+    let mut convs = conversions(&fmt).into_iter();
+    let mut args = vec![];
+    let mut arg = print_fmt.args;
+    // The width and the precision given by `*` arguments for the next conversion.
+    let mut width = None;
+    let mut precision = None;
+    while !arg.is_null() {
+        let mut conv = convs.next().unwrap_or(Conversion {
+            alt: false,
+            zero: false,
+            left: false,
+            width: 0,
+            precision: None,
+            long: true,
+            conv: 'd',
+            ext: None,
+        });
+        // A field that can't be read spoils only the argument using it.
+        match conv.conv {
+            '*' => width = eval(arg, ctx).ok().flatten(),
+            '.' => precision = eval(arg, ctx).ok().flatten(),
+            _ => {
+                if let Some(width) = width.take() {
+                    // A negative width is the `-` flag with the absolute width, as in C.
+                    let width = width as i32;
+                    conv.left |= width < 0;
+                    conv.width = width.unsigned_abs() as usize;
+                }
+                if let Some(precision) = precision.take() {
+                    // A negative precision is taken as if it were omitted, as in C.
+                    conv.precision = (precision as i32 >= 0).then_some(precision as usize);
+                }
+                let value = match render_value(arg, &conv, ctx) {
+                    Ok(value) => value.unwrap_or_else(|| "?".to_string()),
+                    Err(_) => FAILED_TO_PARSE.to_string(),
+                };
+                args.push((arg_name(arg), value));
+            }
+        }
+        arg = (*arg).next;
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders `val` with the only conversion of `fmt`.
+    fn number(fmt: &str, val: u64) -> String {
+        let convs = conversions(fmt);
+        assert_eq!(convs.len(), 1, "{fmt}");
+        convs[0].render_number(val)
+    }
+
+    /// Renders `s` with the only conversion of `fmt`.
+    fn string(fmt: &str, s: &str) -> String {
+        let convs = conversions(fmt);
+        assert_eq!(convs.len(), 1, "{fmt}");
+        convs[0].pad(s.to_string())
+    }
+
+    #[test]
+    fn flags_and_width() {
+        for (fmt, val, expected) in [
+            ("%d", 42, "42"),
+            ("%d", -42i64 as u64, "-42"),
+            ("%5d", 42, "   42"),
+            ("%-5d|", 42, "42   "),
+            ("%05d", 42, "00042"),
+            ("%05d", -42i64 as u64, "-0042"),
+            ("%x", 255, "ff"),
+            ("%#x", 255, "0xff"),
+            ("%#06x", 255, "0x00ff"),
+            ("%X", 255, "FF"),
+            ("%#X", 255, "0XFF"),
+            ("%o", 8, "10"),
+            ("%u", u64::MAX, "4294967295"),
+            ("%lu", u64::MAX, "18446744073709551615"),
+            ("%lld", -1i64 as u64, "-1"),
+            ("%c", b'a' as u64, "a"),
+            ("%3c", b'a' as u64, "  a"),
+        ] {
+            assert_eq!(number(fmt, val), expected, "{fmt}");
+        }
+    }
+
+    #[test]
+    fn precision() {
+        for (fmt, val, expected) in [
+            ("%.3d", 7, "007"),
+            ("%5.3d", 7, "  007"),
+            ("%-5.3d|", 7, "007  "),
+            ("%.3d", -7i64 as u64, "-007"),
+            ("%.0d", 0, ""),
+            ("%.d", 0, ""),
+            ("%.0d", 5, "5"),
+            // The `0` flag is ignored with a precision.
+            ("%05.3d", 7, "  007"),
+            ("%#.4x", 255, "0x00ff"),
+        ] {
+            assert_eq!(number(fmt, val), expected, "{fmt}");
+        }
+
+        for (fmt, s, expected) in [
+            ("%s", "hello", "hello"),
+            ("%.2s", "hello", "he"),
+            ("%.0s", "hello", ""),
+            ("%.10s", "hello", "hello"),
+            ("%4.2s", "hello", "  he"),
+            ("%-4.2s", "hello", "he  "),
+            ("%7s", "hello", "  hello"),
+            ("%-7s", "hello", "hello  "),
+            // The `0` flag doesn't apply to strings.
+            ("%07s", "hello", "  hello"),
+        ] {
+            assert_eq!(string(fmt, s), expected, "{fmt}");
+        }
+    }
+
+    #[test]
+    fn stars_and_percent() {
+        let convs: Vec<_> = conversions("%% %*d %.*s %*.*x %%d %p %pS %lu")
+            .iter()
+            .map(|conv| (conv.conv, conv.ext, conv.long))
+            .collect();
+        assert_eq!(
+            convs,
+            [
+                ('*', None, false),
+                ('d', None, false),
+                ('.', None, false),
+                ('s', None, false),
+                ('*', None, false),
+                ('.', None, false),
+                ('x', None, false),
+                ('p', None, true),
+                ('p', Some('S'), true),
+                ('u', None, true),
+            ]
+        );
+        assert!(conversions("100%% done").is_empty());
+    }
+
+    #[test]
+    fn print_symbolic() {
+        let mapping = Mapping::Symbolic(vec![(0, "RUNNING".to_string()), (1, "S".to_string())]);
+        for (val, expected) in [(0, "RUNNING"), (1, "S"), (5, "0x5")] {
+            assert_eq!(mapping.render(val), expected);
+        }
+    }
+
+    #[test]
+    fn print_flags() {
+        let mapping = Mapping::Flags {
+            delim: "|".to_string(),
+            flags: vec![
+                (0, "NONE".to_string()),
+                (0x1, "A".to_string()),
+                (0x2, "B".to_string()),
+                (0xc, "CD".to_string()),
+            ],
+        };
+        for (val, expected) in [
+            (0, "NONE"),
+            (0x1, "A"),
+            (0x3, "A|B"),
+            (0xc, "CD"),
+            (0x4, "0x4"),
+            (0x13, "A|B|0x10"),
+        ] {
+            assert_eq!(mapping.render(val), expected);
+        }
+    }
+
+    #[test]
+    fn values_of_mappings() {
+        for (value, expected) in [
+            ("42", Some(42)),
+            (" 0x1f ", Some(0x1f)),
+            ("0XFF", Some(0xff)),
+            ("-1", Some(u64::MAX)),
+            ("foo", None),
+        ] {
+            assert_eq!(parse_value(value), expected, "{value}");
+        }
+    }
+
+    /// Creates a print argument reading the field `name`.
+    fn field_arg(
+        name: &std::ffi::CStr,
+        next: *mut bindings::tep_print_arg,
+    ) -> bindings::tep_print_arg {
+        let mut arg: bindings::tep_print_arg = unsafe { std::mem::zeroed() };
+        arg.next = next;
+        arg.type_ = bindings::tep_print_arg_type_TEP_PRINT_FIELD;
+        arg.__bindgen_anon_1.field.name = name.as_ptr() as *mut _;
+        arg
+    }
+
+    #[test]
+    fn unreadable_fields() {
+        // `name` is a string field, which can't be read as a number.
+        let mut pid = field_arg(c"pid", std::ptr::null_mut());
+        let mut name = field_arg(c"name", &mut pid);
+        let mut comm = field_arg(c"comm", &mut name);
+        let mut event: bindings::tep_event = unsafe { std::mem::zeroed() };
+        event.print_fmt.format = c"comm=%s name=%d pid=%d".as_ptr() as *mut _;
+        event.print_fmt.args = &mut comm;
+
+        let number = |field: &str| match field {
+            "pid" => Ok(42),
+            _ => Err(crate::Error::FindField),
+        };
+        let raw = |field: &str| match field {
+            "comm" | "name" => Ok(b"bash\0".to_vec()),
+            _ => Err(crate::Error::FindField),
+        };
+        let function = |_| None;
+        let ctx = ArgContext {
+            number: &number,
+            raw: &raw,
+            function: &function,
+        };
+        let args = unsafe { print_args(&mut event, &ctx) }.unwrap();
+        assert_eq!(
+            args,
+            [
+                ("comm".to_string(), "bash".to_string()),
+                ("name".to_string(), FAILED_TO_PARSE.to_string()),
+                ("pid".to_string(), "42".to_string()),
+            ]
+        );
+    }
+}
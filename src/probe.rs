@@ -0,0 +1,66 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checking files without opening inputs.
+
+use crate::bindings;
+use crate::header::FileHeader;
+use crate::Error;
+use crate::Input;
+use crate::Result;
+
+/// A summary of a `trace.dat` file returned by [Input::probe].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeResult {
+    /// Version of the file format (6 or 7).
+    pub version: u32,
+    /// Number of CPUs with per-CPU data sections in the top-level buffer.
+    pub cpus: usize,
+    /// Trace clock used for recording (e.g. `local`), if it's recorded.
+    pub clock: Option<String>,
+    /// Name of the algorithm compressing the sections of the file (e.g. `zstd`), or `None` if
+    /// the file is not compressed.
+    pub compression: Option<String>,
+    /// Whether libtracecmd could parse the headers of the file, including the event formats.
+    /// If this is `false`, [Input::new] is expected to fail.
+    pub ready: bool,
+}
+
+impl Input {
+    /// Checks the file at `path` and summarizes it without creating an [Input].
+    ///
+    /// The header is read by this crate, and then libtracecmd parses the headers with
+    /// `tracecmd_open_head`, which doesn't initialize the per-CPU data, to tell whether the file
+    /// can be opened. The handle is closed before this returns. Fails with the same errors as
+    /// [Input::validate] if the header is malformed.
+    pub fn probe(path: &str) -> Result<ProbeResult> {
+        let header = FileHeader::read(path)?;
+
+        let c_path = std::ffi::CString::new(path).map_err(|_| Error::Open)?;
+        let handle = unsafe { bindings::tracecmd_open_head(c_path.as_ptr(), 0) };
+        let ready = !handle.is_null();
+        if ready {
+            unsafe { bindings::tracecmd_close(handle) };
+        }
+
+        Ok(ProbeResult {
+            version: header.version,
+            cpus: header.cpu_data.len(),
+            clock: header.clock,
+            compression: header.compression,
+            ready,
+        })
+    }
+}
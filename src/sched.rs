@@ -0,0 +1,323 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Analyses of scheduler and task events.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::iterate_events;
+use crate::try_iterate_events;
+use crate::Error;
+use crate::HandleRef;
+use crate::Input;
+use crate::Record;
+use crate::Result;
+
+/// Fields of a `sched_wakeup` or `sched_wakeup_new` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchedWakeup {
+    /// Command name of the woken task.
+    pub comm: String,
+    /// PID of the woken task.
+    pub pid: i32,
+    /// Priority of the woken task.
+    pub prio: i32,
+    /// CPU that the woken task is queued on.
+    pub target_cpu: i32,
+    /// Whether the wakeup succeeded. `None` on kernels without the `success` field (5.x and
+    /// later, where the event is emitted only for successful wakeups).
+    pub success: Option<bool>,
+    /// PID of the task that woke up the task, which is the `common_pid` of the record.
+    pub waker_pid: i32,
+    /// Whether the event is `sched_wakeup_new`, i.e. the first wakeup of a new task.
+    pub new_task: bool,
+}
+
+/// Fields of a `sched_migrate_task` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchedMigrate {
+    /// Command name of the migrated task.
+    pub comm: String,
+    /// PID of the migrated task.
+    pub pid: i32,
+    /// Priority of the migrated task.
+    pub prio: i32,
+    /// CPU that the task is migrated from.
+    pub orig_cpu: i32,
+    /// CPU that the task is migrated to.
+    pub dest_cpu: i32,
+}
+
+impl Record<'_> {
+    /// Decodes a `sched_wakeup`, `sched_wakeup_new` or `sched_waking` record.
+    ///
+    /// Returns [Error::FindField] if the record is not one of these events.
+    pub fn sched_wakeup(&self, handle: &HandleRef) -> Result<SchedWakeup> {
+        let event = self.event(handle)?;
+        Ok(SchedWakeup {
+            comm: event.read_field_str(self, "comm")?,
+            pid: event.read_field_u64(self, "pid")? as i32,
+            prio: event.read_field_u64(self, "prio")? as i32,
+            target_cpu: event.read_field_u64(self, "target_cpu")? as i32,
+            success: event
+                .read_field_u64(self, "success")
+                .ok()
+                .map(|success| success != 0),
+            waker_pid: handle.pid(self),
+            new_task: event.name_str() == "sched_wakeup_new",
+        })
+    }
+
+    /// Decodes a `sched_migrate_task` record.
+    ///
+    /// Returns [Error::FindField] if the record is not a `sched_migrate_task` event.
+    pub fn sched_migrate(&self, handle: &HandleRef) -> Result<SchedMigrate> {
+        let event = self.event(handle)?;
+        Ok(SchedMigrate {
+            comm: event.read_field_str(self, "comm")?,
+            pid: event.read_field_u64(self, "pid")? as i32,
+            prio: event.read_field_u64(self, "prio")? as i32,
+            orig_cpu: event.read_field_u64(self, "orig_cpu")? as i32,
+            dest_cpu: event.read_field_u64(self, "dest_cpu")? as i32,
+        })
+    }
+}
+
+impl Input {
+    /// Computes the wakeup latency of tasks, i.e. the time from `sched_wakeup` (or
+    /// `sched_wakeup_new`) of a task until a `sched_switch` switches to it, as
+    /// `trace-cmd report --profile` does.
+    ///
+    /// Latencies are returned in the order in which the tasks started running, paired with the
+    /// pids of the tasks. If a task is woken up again before it runs, the latency is measured
+    /// from the first wakeup. Wakeups without a following `sched_switch` to the task (e.g. at
+    /// the end of the trace, or when the switch was dropped) are not included. The trace needs
+    /// to be recorded with `trace-cmd record -e sched_wakeup -e sched_wakeup_new -e
+    /// sched_switch`.
+    pub fn wakeup_latencies(&mut self) -> Result<Vec<(i32, Duration)>> {
+        // Timestamps of wakeups not followed by a switch yet, keyed by pids.
+        let mut wakeups: HashMap<i32, u64> = HashMap::new();
+        let mut latencies = vec![];
+        try_iterate_events(self, |rec, _cpu| {
+            let event = self.find_event(rec)?;
+            let (field, is_wakeup) = match event.name_str() {
+                "sched_wakeup" | "sched_wakeup_new" => ("pid", true),
+                "sched_switch" => ("next_pid", false),
+                _ => return Ok(true),
+            };
+            let pid = event.read_field_u64(rec, field)? as i32;
+            if is_wakeup {
+                wakeups.entry(pid).or_insert(rec.ts());
+            } else if let Some(ts) = wakeups.remove(&pid) {
+                latencies.push((pid, Duration::from_nanos(rec.ts().saturating_sub(ts))));
+            }
+            Ok(true)
+        })?;
+
+        Ok(latencies)
+    }
+
+    /// Estimates the offset of the clock of each CPU relative to CPU 0 in nanoseconds, which is
+    /// non-zero if CPUs have drifting clocks (e.g. unsynchronized TSCs with the `x86-tsc` clock).
+    ///
+    /// The estimation relies on causality between scheduler events: a task woken up with
+    /// `sched_wakeup` (or `sched_wakeup_new`) on a CPU can't be switched in by `sched_switch` on
+    /// another CPU before the wakeup. So, the smallest delay observed from a wakeup on CPU 0 to
+    /// the switch on CPU `n` is an upper bound of the offset of CPU `n`, and the smallest delay
+    /// from CPU `n` to CPU 0 bounds it from below. The returned offset is the middle of the two
+    /// bounds, or the nearest value to 0 satisfying the only bound. CPU 0 and CPUs without any
+    /// wakeup from or to CPU 0 get 0.
+    ///
+    /// The accuracy is limited by the shortest wakeup latencies between CPUs, which are usually
+    /// a few microseconds. Offsets larger than typical wakeup latencies make records appear
+    /// out of causal order, so pairs of events may be missed and the estimate may be off. The
+    /// trace needs to be recorded with `trace-cmd record -e sched_wakeup -e sched_switch`.
+    pub fn cpu_clock_skew(&mut self) -> Result<Vec<i64>> {
+        let nr_cpus = self.header_cpus().max(1);
+        // Timestamps and CPUs of the last wakeups not followed by a switch yet, keyed by pids.
+        let mut wakeups: HashMap<i32, (u64, usize)> = HashMap::new();
+        // Smallest delays from CPU 0 to each CPU and from each CPU to CPU 0.
+        let mut from_cpu0: Vec<Option<i64>> = vec![None; nr_cpus];
+        let mut to_cpu0: Vec<Option<i64>> = vec![None; nr_cpus];
+        try_iterate_events(self, |rec, cpu| {
+            let event = self.find_event(rec)?;
+            let (field, is_wakeup) = match event.name_str() {
+                "sched_wakeup" | "sched_wakeup_new" => ("pid", true),
+                "sched_switch" => ("next_pid", false),
+                _ => return Ok(true),
+            };
+            let pid = event.read_field_u64(rec, field)? as i32;
+            let cpu = cpu as usize;
+            if cpu >= nr_cpus {
+                return Ok(true);
+            }
+            if is_wakeup {
+                wakeups.insert(pid, (rec.ts(), cpu));
+                return Ok(true);
+            }
+            let Some((wakeup_ts, wakeup_cpu)) = wakeups.remove(&pid) else {
+                return Ok(true);
+            };
+            let delay = rec.ts() as i64 - wakeup_ts as i64;
+            let min = match (wakeup_cpu, cpu) {
+                (0, 0) => return Ok(true),
+                (0, cpu) => &mut from_cpu0[cpu],
+                (wakeup_cpu, 0) => &mut to_cpu0[wakeup_cpu],
+                _ => return Ok(true),
+            };
+            *min = Some(min.map_or(delay, |min| min.min(delay)));
+            Ok(true)
+        })?;
+
+        Ok(from_cpu0
+            .into_iter()
+            .zip(to_cpu0)
+            .map(|bounds| match bounds {
+                (Some(upper), Some(lower)) => (upper - lower) / 2,
+                (Some(upper), None) => upper.min(0),
+                (None, Some(lower)) => (-lower).max(0),
+                (None, None) => 0,
+            })
+            .collect())
+    }
+
+    /// Enables or disables learning command names from `sched_switch` (`prev_comm` and
+    /// `next_comm`) and `sched_wakeup` (`comm`) records for [Input::comm_from_pid].
+    ///
+    /// When enabled, names are learned from every record read from this input before the
+    /// record is passed to a callback or an iterator, so [Input::comm_from_pid] is up to date
+    /// during the iteration. This costs an event lookup per record. Alternatively,
+    /// [Input::learn_comms] learns names in a separate pass before the iteration.
+    pub fn set_track_comms(&mut self, track: bool) {
+        self.track_comms = track;
+    }
+
+    /// Learns command names from all `sched_switch` and `sched_wakeup` records of the file for
+    /// [Input::comm_from_pid], by reading the file again with a separate cursor (see
+    /// [Input::reopen]). The cursor of `self` is not moved.
+    ///
+    /// Each pid is mapped to its last name in the file.
+    pub fn learn_comms(&self) -> Result<()> {
+        let input = self.reopen()?;
+        let ret = iterate_events(&input, |rec, _cpu| {
+            // Records of `input` have the same event formats as `self`.
+            self.observe_comms(rec);
+            0
+        });
+        ret.map_err(Error::Iterate)
+    }
+
+    /// Gets the command name of the task `pid`, preferring the name learned from scheduler
+    /// events over the saved cmdlines.
+    ///
+    /// The saved cmdlines table is filled by the kernel with a limited number of entries and
+    /// can be stale, e.g. for short-lived processes or tasks that were renamed, while scheduler
+    /// events carry the name at the time of the event. Names are learned only with
+    /// [Input::set_track_comms] (during an iteration) or [Input::learn_comms] (in a prior
+    /// pass). Without them, or for pids that don't appear in scheduler events, this falls back
+    /// to the saved cmdlines like [HandleRef::comm_lossy].
+    pub fn comm_from_pid(&self, pid: i32) -> Option<String> {
+        if let Some(comm) = self.observed_comms.borrow().get(&pid) {
+            return Some(comm.clone());
+        }
+        self.handle_ref().ok()?.comm_lossy(pid)
+    }
+
+    /// Learns command names from `rec` if it's a `sched_switch` or `sched_wakeup` record.
+    pub(crate) fn observe_comms(&self, rec: &Record) {
+        let Ok(event) = self.find_event(rec) else {
+            return;
+        };
+        let fields: &[(&str, &str)] = match event.name_str() {
+            "sched_switch" => &[("prev_pid", "prev_comm"), ("next_pid", "next_comm")],
+            "sched_wakeup" => &[("pid", "comm")],
+            _ => return,
+        };
+        let mut comms = self.observed_comms.borrow_mut();
+        for (pid, comm) in fields {
+            if let (Ok(pid), Ok(comm)) = (
+                event.read_field_u64(rec, pid),
+                event.read_field_str(rec, comm),
+            ) {
+                comms.insert(pid as i32, comm);
+            }
+        }
+    }
+
+    /// Gets the thread group id (i.e. the pid of the process) of the task `pid`.
+    ///
+    /// Neither libtraceevent nor `trace.dat` keeps a table of thread group ids, so the mapping is
+    /// reconstructed from `task_newtask` events (with the `CLONE_THREAD` flag telling whether
+    /// a new task is a thread) and `sched_process_exec` events, which are recorded with
+    /// `trace-cmd record -e task_newtask -e sched_process_exec`. Returns `None` for tasks that
+    /// don't appear in these events, e.g. tasks created before tracing started. A thread created
+    /// by another thread created before tracing started is mapped to the pid of its creator.
+    ///
+    /// The mapping is built on the first call by reading all records of the file again with a
+    /// separate cursor (see [Input::reopen]), so the cursor of `self` is not moved.
+    pub fn tgid_for_pid(&self, pid: i32) -> Option<i32> {
+        self.tgids
+            .get_or_init(|| {
+                self.reopen()
+                    .map(|input| collect_tgids(&input))
+                    .unwrap_or_default()
+            })
+            .get(&pid)
+            .copied()
+    }
+}
+
+/// `CLONE_THREAD` in the `clone_flags` field of `task_newtask`.
+const CLONE_THREAD: u64 = 0x0001_0000;
+
+/// Builds the map from pids to thread group ids for [Input::tgid_for_pid].
+fn collect_tgids(input: &Input) -> HashMap<i32, i32> {
+    let mut tgids = HashMap::new();
+    let Ok(handle) = input.handle_ref() else {
+        return tgids;
+    };
+    let _ = iterate_events(input, |rec, _cpu| {
+        let Ok(event) = input.find_event(rec) else {
+            return 0;
+        };
+        match event.name_str() {
+            "task_newtask" => {
+                if let (Ok(child), Ok(flags)) = (
+                    event.read_field_u64(rec, "pid"),
+                    event.read_field_u64(rec, "clone_flags"),
+                ) {
+                    let child = child as i32;
+                    // The event is emitted by the task calling clone().
+                    let parent = handle.pid(rec);
+                    let tgid = if flags & CLONE_THREAD != 0 {
+                        tgids.get(&parent).copied().unwrap_or(parent)
+                    } else {
+                        child
+                    };
+                    tgids.insert(child, tgid);
+                }
+            }
+            "sched_process_exec" => {
+                if let Ok(pid) = event.read_field_u64(rec, "pid") {
+                    // A task calling execve() becomes the leader of its thread group.
+                    tgids.insert(pid as i32, pid as i32);
+                }
+            }
+            _ => {}
+        }
+        0
+    });
+    tgids
+}
@@ -0,0 +1,162 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Summaries and reports of whole traces.
+
+use std::collections::BTreeMap;
+
+use crate::count_cpu;
+use crate::dropped_events;
+use crate::try_iterate_events;
+use crate::Input;
+use crate::Record;
+use crate::Result;
+
+/// A summary of a trace, which can be kept after the original `trace.dat` is gone.
+///
+/// With the `serde` feature, it can be serialized (e.g. into JSON) and deserialized.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceSummary {
+    /// Number of records of each event, keyed by event names.
+    pub event_counts: BTreeMap<String, u64>,
+    /// Timestamp of the first record, or `None` if the trace has no records.
+    pub first_ts: Option<u64>,
+    /// Timestamp of the last record, or `None` if the trace has no records.
+    pub last_ts: Option<u64>,
+    /// Number of CPUs of the traced machine. See [Input::header_cpus].
+    pub cpus: usize,
+    /// `uname` of the traced machine, if it's recorded.
+    pub uname: Option<String>,
+}
+
+impl TraceSummary {
+    /// Creates a summary of `input` with the information in the file header only.
+    fn from_header(input: &Input) -> Self {
+        TraceSummary {
+            cpus: input.header_cpus(),
+            uname: input.file_header().and_then(|header| header.uname.clone()),
+            ..Default::default()
+        }
+    }
+
+    /// Adds `rec` to the event counts and the time range. Both [Input::summarize] and
+    /// [Input::report] use this, so that they always agree.
+    fn add(&mut self, input: &Input, rec: &Record) -> Result<()> {
+        let event = input.find_event(rec)?;
+        *self.event_counts.entry(event.name).or_default() += 1;
+
+        let ts = rec.ts();
+        self.first_ts = Some(self.first_ts.map_or(ts, |first| first.min(ts)));
+        self.last_ts = Some(self.last_ts.map_or(ts, |last| last.max(ts)));
+        Ok(())
+    }
+}
+
+/// Options of [Input::report].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportOptions {
+    /// Whether to iterate over records. Without it, only the information in the file header
+    /// (the number of CPUs and `uname`) is reported, which is fast for any size of trace.
+    /// Defaults to `true`.
+    pub records: bool,
+    /// Number of the most frequent events in [Report::top_events]. Defaults to 10.
+    pub top_n: usize,
+    /// Whether to count records per CPU. Defaults to `true`.
+    pub per_cpu_counts: bool,
+    /// Whether to count dropped events. Defaults to `true`.
+    pub dropped_events: bool,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            records: true,
+            top_n: 10,
+            per_cpu_counts: true,
+            dropped_events: true,
+        }
+    }
+}
+
+/// A report of a trace for a quick look, created by [Input::report].
+///
+/// With the `serde` feature, it can be serialized (e.g. into JSON) and deserialized.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    /// Event counts, the time range, the number of CPUs and `uname`. Event counts and the time
+    /// range are empty if [ReportOptions::records] is disabled.
+    pub summary: TraceSummary,
+    /// The most frequent events with their counts, in descending order of counts.
+    pub top_events: Vec<(String, u64)>,
+    /// Number of records of each CPU. See [Input::per_cpu_counts].
+    pub per_cpu_counts: Option<Vec<u64>>,
+    /// Number of events dropped by the ring buffer. See [Input::total_dropped_events].
+    pub dropped_events: Option<u64>,
+}
+
+impl Input {
+    /// Creates a report of the trace, combining [Input::summarize], [Input::per_cpu_counts],
+    /// [Input::total_dropped_events] and the most frequent events.
+    ///
+    /// All of them are computed in a single pass over the records, which can be skipped with
+    /// [ReportOptions::records]. Each record is counted by the same code as in the individual
+    /// methods, so the results are the same as what each of them returns on a freshly opened
+    /// input. Like them, this reads the records from the current positions of the CPUs' cursors
+    /// and leaves the cursors at the end, so calling them one after another on the same input
+    /// gives empty results after the first call.
+    pub fn report(&mut self, opts: ReportOptions) -> Result<Report> {
+        let mut report = Report {
+            summary: TraceSummary::from_header(self),
+            ..Default::default()
+        };
+        if !opts.records {
+            return Ok(report);
+        }
+
+        let summary = &mut report.summary;
+        let mut per_cpu_counts = vec![0; summary.cpus];
+        let mut dropped = self.total_overruns();
+        try_iterate_events(self, |rec, cpu| {
+            summary.add(self, rec)?;
+            count_cpu(&mut per_cpu_counts, cpu);
+            dropped += dropped_events(rec);
+            Ok(true)
+        })?;
+
+        let mut top_events: Vec<_> = summary
+            .event_counts
+            .iter()
+            .map(|(name, count)| (name.clone(), *count))
+            .collect();
+        top_events.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+        top_events.truncate(opts.top_n);
+        report.top_events = top_events;
+        report.per_cpu_counts = opts.per_cpu_counts.then_some(per_cpu_counts);
+        report.dropped_events = opts.dropped_events.then_some(dropped);
+        Ok(report)
+    }
+
+    /// Iterates over all records and summarizes the trace.
+    pub fn summarize(&mut self) -> Result<TraceSummary> {
+        let mut summary = TraceSummary::from_header(self);
+        try_iterate_events(self, |rec, _cpu| {
+            summary.add(self, rec)?;
+            Ok(true)
+        })?;
+
+        Ok(summary)
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of system call events.
+
+use std::collections::HashMap;
+
+use crate::iterate_events;
+use crate::Error;
+use crate::HandleRef;
+use crate::Input;
+use crate::Record;
+use crate::Result;
+
+impl Record<'_> {
+    /// Reads the system call number of a system call event.
+    ///
+    /// This supports the generic `raw_syscalls` events (`sys_enter` and `sys_exit`), which have
+    /// the number in the `id` field, as well as the per-syscall events of the `syscalls` system
+    /// (e.g. `sys_enter_openat`), which have it in the `__syscall_nr` field. Returns
+    /// [Error::FindField] for other events.
+    pub fn syscall_nr(&self, handle: &HandleRef) -> Result<i64> {
+        let event = self.event(handle)?;
+        let field = match event.name_str() {
+            "sys_enter" | "sys_exit" => "id",
+            name if name.starts_with("sys_enter_") || name.starts_with("sys_exit_") => {
+                "__syscall_nr"
+            }
+            _ => return Err(Error::FindField),
+        };
+        Ok(event.read_field_u64(self, field)? as i64)
+    }
+}
+
+impl Input {
+    /// Gets the name of the system call numbered `nr` (e.g. `openat`).
+    ///
+    /// The file doesn't contain the system call table of the traced architecture, so names are
+    /// learned from records of the per-syscall events of the `syscalls` system (e.g.
+    /// `sys_enter_openat`), which carry the number. Returns `None` for system calls that don't
+    /// appear in such records, e.g. when only `raw_syscalls` events were recorded.
+    ///
+    /// The mapping is built on the first call by reading all records of the file again with a
+    /// separate cursor (see [Input::reopen]), so the cursor of `self` is not moved.
+    pub fn syscall_name(&self, nr: i64) -> Option<&str> {
+        self.syscall_names
+            .get_or_init(|| {
+                self.reopen()
+                    .map(|input| collect_syscall_names(&input))
+                    .unwrap_or_default()
+            })
+            .get(&nr)
+            .map(String::as_str)
+    }
+}
+
+/// Builds the map from system call numbers to names for [Input::syscall_name].
+fn collect_syscall_names(input: &Input) -> HashMap<i64, String> {
+    let mut names = HashMap::new();
+    // Whether each event type is a per-syscall event, keyed by type id.
+    let mut ids = HashMap::new();
+    let _ = iterate_events(input, |rec, _cpu| {
+        let Ok(event) = input.find_event(rec) else {
+            return 0;
+        };
+        let name = ids.entry(event.id()).or_insert_with(|| {
+            event
+                .name_str()
+                .strip_prefix("sys_enter_")
+                .map(str::to_string)
+        });
+        if let Some(name) = name {
+            if let Ok(nr) = event.read_field_u64(rec, "__syscall_nr") {
+                names.entry(nr as i64).or_insert_with(|| name.clone());
+            }
+        }
+        0
+    });
+    names
+}
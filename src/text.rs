@@ -0,0 +1,118 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Text output in the same format as `trace-cmd report`.
+
+use std::io::Write;
+
+use crate::bindings;
+use crate::format_ts;
+use crate::try_iterate_events;
+use crate::Error;
+use crate::HandleRef;
+use crate::Input;
+use crate::Record;
+use crate::Result;
+use crate::TraceSeq;
+
+/// Options of [Input::report_text].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextOptions {
+    /// Whether to print the CPU of each record (e.g. `[002]`). Defaults to `true`.
+    pub cpu: bool,
+    /// Whether to print the latency flags of each record (e.g. `d.h1.`), as
+    /// `trace-cmd report -l` does. Defaults to `false`.
+    pub latency: bool,
+    /// Whether to print timestamps relative to the first record of the trace. See
+    /// [Record::ts_relative]. Defaults to `false`.
+    pub relative_ts: bool,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        TextOptions {
+            cpu: true,
+            latency: false,
+            relative_ts: false,
+        }
+    }
+}
+
+impl Input {
+    /// Writes all records to `w` in timestamp order, one line per record, in the format of
+    /// `trace-cmd report`:
+    ///
+    /// ```text
+    ///           <idle>-0     [001]  1234.567890: sched_switch: prev_comm=swapper/1 ...
+    /// ```
+    ///
+    /// Command names are taken from the saved cmdlines and shown as `<...>` if they weren't
+    /// recorded. Errors from `w` are returned as [Error::Io].
+    pub fn report_text<W: Write>(&mut self, mut w: W, opts: TextOptions) -> Result<()> {
+        let handle = self.handle_ref()?;
+        try_iterate_events(self, |rec, cpu| {
+            let line = format_line(self, &handle, rec, cpu, &opts)?;
+            writeln!(w, "{line}").map_err(Error::Io)?;
+            Ok(true)
+        })?;
+        w.flush().map_err(Error::Io)
+    }
+}
+
+/// Formats a line of [Input::report_text] for `rec`.
+fn format_line(
+    input: &Input,
+    handle: &HandleRef,
+    rec: &Record,
+    cpu: i32,
+    opts: &TextOptions,
+) -> Result<String> {
+    let event = input.find_event(rec)?;
+    let pid = handle.pid(rec);
+    let comm = handle
+        .comm_lossy(pid)
+        .unwrap_or_else(|| "<...>".to_string());
+    let ts = if opts.relative_ts {
+        rec.ts_relative(input)
+    } else {
+        rec.ts()
+    };
+
+    let mut line = format!("{comm:>16}-{pid:<5}");
+    if opts.cpu {
+        line += &format!(" [{cpu:03}]");
+    }
+    if opts.latency {
+        line += " ";
+        line += &print_field(handle, rec, bindings::TEP_PRINT_LATENCY);
+    }
+    line += &format!(" {:>12}: {}: ", format_ts(ts), event.name);
+    line += &print_field(handle, rec, bindings::TEP_PRINT_INFO);
+    Ok(line.trim_end().to_string())
+}
+
+/// Prints one of the NUL-terminated `TEP_PRINT_*` fields of `rec` with `tep_print_event`.
+fn print_field(handle: &HandleRef, rec: &Record, field: &[u8]) -> String {
+    let mut seq = TraceSeq::new();
+    unsafe {
+        bindings::tep_print_event(
+            handle.0,
+            seq.as_mut_ptr(),
+            rec.0,
+            c"%s".as_ptr(),
+            field.as_ptr(),
+        );
+    }
+    seq.contents_lossy()
+}
@@ -0,0 +1,68 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of the ftrace tracer used for recording.
+
+use crate::bindings;
+use crate::iterate_events;
+use crate::Input;
+use crate::STOP;
+
+impl Input {
+    /// Gets the name of the ftrace tracer that the trace was recorded with: `function_graph` or
+    /// `function`.
+    ///
+    /// `trace.dat` doesn't store the name of the tracer, so this looks for a record of ftrace's
+    /// own events that the tracers emit (`funcgraph_entry`/`funcgraph_exit` and `function`
+    /// respectively), like [Input::has_function_graph]. The file is scanned from the beginning
+    /// with a new handle opened by [Input::reopen], so the cursors of `self` are not moved and
+    /// records of other events mixed with the tracer's don't matter. The scan stops at the
+    /// first record of the tracers.
+    ///
+    /// Returns `None` if there is no such record, e.g. only trace events were enabled (the
+    /// `nop` tracer), or the file can't be read again.
+    pub fn tracer_name(&self) -> Option<String> {
+        let input = self.reopen().ok()?;
+        let handle = input.handle_ref().ok()?;
+        let tracers: Vec<(i32, &str)> = [
+            (c"funcgraph_entry", "function_graph"),
+            (c"funcgraph_exit", "function_graph"),
+            (c"function", "function"),
+        ]
+        .into_iter()
+        .filter_map(|(name, tracer)| {
+            let event = unsafe {
+                bindings::tep_find_event_by_name(handle.0, c"ftrace".as_ptr(), name.as_ptr())
+            };
+            (!event.is_null()).then(|| (unsafe { (*event).id }, tracer))
+        })
+        .collect();
+        if tracers.is_empty() {
+            return None;
+        }
+
+        let mut found = None;
+        let _ = iterate_events(&input, |rec, _cpu| {
+            let id = unsafe { bindings::tep_data_type(handle.0, rec.0) };
+            match tracers.iter().find(|(tracer_id, _)| *tracer_id == id) {
+                Some((_, tracer)) => {
+                    found = Some(tracer.to_string());
+                    STOP
+                }
+                None => 0,
+            }
+        });
+        found
+    }
+}
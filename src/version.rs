@@ -0,0 +1,49 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Version and capabilities of libtracecmd.
+
+/// Returns the `(major, minor, patch)` version of libtracecmd that this crate was built against,
+/// as reported by pkg-config.
+///
+/// Components that can't be parsed (e.g. a missing patch version) are reported as 0.
+pub fn libtracecmd_version() -> (u32, u32, u32) {
+    let mut parts = env!("LIBTRACECMD_VERSION")
+        .split('.')
+        .map(|p| p.parse().unwrap_or(0));
+    (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
+}
+
+fn at_least(version: (u32, u32, u32)) -> bool {
+    libtracecmd_version() >= version
+}
+
+/// Returns whether libtracecmd can read `trace.dat` files of version 7, whose sections may be
+/// compressed (e.g. with zlib or zstd). Introduced in libtracecmd 1.0.
+pub fn supports_compression() -> bool {
+    at_least((1, 0, 0))
+}
+
+/// Returns whether libtracecmd provides `tracecmd_iterate_events_reverse` to iterate events
+/// from the newest to the oldest. Introduced in libtracecmd 1.4.
+///
+/// The function is linked only if the crate was built against such a version, which the build
+/// script detects.
+pub fn supports_iterate_reverse() -> bool {
+    cfg!(libtracecmd_iterate_reverse)
+}